@@ -1,7 +1,36 @@
 use crate::errors::FastSocketError;
-use serde::{Deserialize, Serialize};
+use crate::outbound_queue::SlowConsumerPolicy;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
+/// Live counters of an app, shared by every copy of its settings.
+#[derive(Debug, Default)]
+pub struct AppState {
+    connections: AtomicU64,
+    dropped_messages: AtomicU64,
+}
+
+impl AppState {
+    /// Counts messages the slow consumer policy dropped on one of the app's connections.
+    #[inline]
+    pub fn record_dropped(&self, count: u64) {
+        self.dropped_messages.fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+/// A connection slot taken from the app's capacity, given back on drop.
+#[derive(Debug)]
+pub struct AppConnection {
+    state: Arc<AppState>,
+}
+
+impl Drop for AppConnection {
+    fn drop(&mut self) {
+        self.state.connections.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct App {
     id: String,
@@ -11,13 +40,42 @@ pub struct App {
     host: String,
     path: String,
     capacity: u64,
-    connection_count: u64,
+    #[serde(skip)]
+    state: Arc<AppState>,
     flags: u8,
+    #[serde(default = "App::default_max_batch_size")]
+    max_batch_size: usize,
+    #[serde(default = "App::default_activity_timeout", deserialize_with = "App::deserialize_timeout")]
+    activity_timeout: u64,
+    #[serde(default = "App::default_pong_timeout", deserialize_with = "App::deserialize_timeout")]
+    pong_timeout: u64,
+    #[serde(default)]
+    slow_consumer_policy: SlowConsumerPolicy,
+    #[serde(default = "App::default_max_outbound_queue")]
+    max_outbound_queue: usize,
+    #[serde(default = "App::default_max_queue_overflows")]
+    max_queue_overflows: u32,
+    #[serde(default = "App::default_max_presence_member_size")]
+    max_presence_member_size: usize,
+    #[serde(default = "App::default_max_presence_members_per_channel")]
+    max_presence_members_per_channel: usize,
+    #[serde(default = "App::default_max_event_payload_size")]
+    max_event_payload_size: usize,
 }
 
 impl App {
     const CLIENT_MESSAGES_FLAG: u8 = 1 << 0;
     const STATISTICS_FLAG: u8 = 1 << 1;
+    const DISABLED_FLAG: u8 = 1 << 2;
+    const DEFAULT_MAX_BATCH_SIZE: usize = 10;
+    const DEFAULT_ACTIVITY_TIMEOUT: u64 = 30;
+    const DEFAULT_PONG_TIMEOUT: u64 = 30;
+    const MIN_TIMEOUT: u64 = 1;
+    const DEFAULT_MAX_OUTBOUND_QUEUE: usize = 256;
+    const DEFAULT_MAX_QUEUE_OVERFLOWS: u32 = 3;
+    const DEFAULT_MAX_PRESENCE_MEMBER_SIZE: usize = 2048;
+    const DEFAULT_MAX_PRESENCE_MEMBERS_PER_CHANNEL: usize = 100;
+    const DEFAULT_MAX_EVENT_PAYLOAD_SIZE: usize = 10240;
 
     #[inline]
     pub fn new(
@@ -67,7 +125,16 @@ impl App {
             path,
             capacity,
             flags,
-            connection_count: 0,
+            state: Arc::default(),
+            max_batch_size: Self::DEFAULT_MAX_BATCH_SIZE,
+            activity_timeout: Self::DEFAULT_ACTIVITY_TIMEOUT,
+            pong_timeout: Self::DEFAULT_PONG_TIMEOUT,
+            slow_consumer_policy: SlowConsumerPolicy::default(),
+            max_outbound_queue: Self::DEFAULT_MAX_OUTBOUND_QUEUE,
+            max_queue_overflows: Self::DEFAULT_MAX_QUEUE_OVERFLOWS,
+            max_presence_member_size: Self::DEFAULT_MAX_PRESENCE_MEMBER_SIZE,
+            max_presence_members_per_channel: Self::DEFAULT_MAX_PRESENCE_MEMBERS_PER_CHANNEL,
+            max_event_payload_size: Self::DEFAULT_MAX_EVENT_PAYLOAD_SIZE,
         }))
     }
 
@@ -87,7 +154,16 @@ impl App {
             path: self.path.clone(),
             capacity: self.capacity,
             flags: self.flags,
-            connection_count: self.connection_count,
+            state: self.state.clone(),
+            max_batch_size: self.max_batch_size,
+            activity_timeout: self.activity_timeout,
+            pong_timeout: self.pong_timeout,
+            slow_consumer_policy: self.slow_consumer_policy,
+            max_outbound_queue: self.max_outbound_queue,
+            max_queue_overflows: self.max_queue_overflows,
+            max_presence_member_size: self.max_presence_member_size,
+            max_presence_members_per_channel: self.max_presence_members_per_channel,
+            max_event_payload_size: self.max_event_payload_size,
         }
     }
 
@@ -133,17 +209,129 @@ impl App {
 
     #[inline]
     pub fn get_connection_count(&self) -> u64 {
-        self.connection_count
+        self.state.connections.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub fn get_state(&self) -> &Arc<AppState> {
+        &self.state
+    }
+
+    /// Messages dropped for slow consumers over all of the app's connections.
+    #[inline]
+    pub fn get_dropped_message_count(&self) -> u64 {
+        self.state.dropped_messages.load(Ordering::Relaxed)
+    }
+
+    /// Takes a connection slot, or returns None once the app is at capacity.
+    #[inline]
+    pub fn try_connect(&self) -> Option<AppConnection> {
+        self.state.connections
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                (count < self.capacity).then_some(count + 1)
+            })
+            .ok()?;
+
+        Some(AppConnection {
+            state: self.state.clone(),
+        })
+    }
+
+    #[inline]
+    pub fn get_max_batch_size(&self) -> usize {
+        self.max_batch_size
+    }
+
+    #[inline]
+    pub fn set_max_batch_size(&mut self, max_batch_size: usize) {
+        self.max_batch_size = max_batch_size;
+    }
+
+    /// Seconds of silence before the server pings the client.
+    #[inline]
+    pub fn get_activity_timeout(&self) -> u64 {
+        self.activity_timeout
+    }
+
+    #[inline]
+    pub fn set_activity_timeout(&mut self, activity_timeout: u64) {
+        self.activity_timeout = activity_timeout.max(Self::MIN_TIMEOUT);
+    }
+
+    /// Seconds a client gets to answer a server ping before it's disconnected.
+    #[inline]
+    pub fn get_pong_timeout(&self) -> u64 {
+        self.pong_timeout
+    }
+
+    #[inline]
+    pub fn set_pong_timeout(&mut self, pong_timeout: u64) {
+        self.pong_timeout = pong_timeout.max(Self::MIN_TIMEOUT);
+    }
+
+    #[inline]
+    pub fn get_slow_consumer_policy(&self) -> SlowConsumerPolicy {
+        self.slow_consumer_policy
+    }
+
+    #[inline]
+    pub fn set_slow_consumer_policy(&mut self, slow_consumer_policy: SlowConsumerPolicy) {
+        self.slow_consumer_policy = slow_consumer_policy;
+    }
+
+    /// Messages a client may have pending before the slow consumer policy kicks in.
+    #[inline]
+    pub fn get_max_outbound_queue(&self) -> usize {
+        self.max_outbound_queue
+    }
+
+    #[inline]
+    pub fn set_max_outbound_queue(&mut self, max_outbound_queue: usize) {
+        self.max_outbound_queue = max_outbound_queue;
+    }
+
+    /// Overflows tolerated before a client is disconnected under `SlowConsumerPolicy::Disconnect`.
+    #[inline]
+    pub fn get_max_queue_overflows(&self) -> u32 {
+        self.max_queue_overflows
+    }
+
+    #[inline]
+    pub fn set_max_queue_overflows(&mut self, max_queue_overflows: u32) {
+        self.max_queue_overflows = max_queue_overflows;
+    }
+
+    /// Bytes of `channel_data` a presence member may send when subscribing.
+    #[inline]
+    pub fn get_max_presence_member_size(&self) -> usize {
+        self.max_presence_member_size
+    }
+
+    #[inline]
+    pub fn set_max_presence_member_size(&mut self, max_presence_member_size: usize) {
+        self.max_presence_member_size = max_presence_member_size;
+    }
+
+    /// Distinct users a presence channel accepts, further users get a subscription error.
+    #[inline]
+    pub fn get_max_presence_members_per_channel(&self) -> usize {
+        self.max_presence_members_per_channel
+    }
+
+    #[inline]
+    pub fn set_max_presence_members_per_channel(&mut self, max_presence_members_per_channel: usize) {
+        self.max_presence_members_per_channel = max_presence_members_per_channel;
     }
 
+    /// Bytes of event data accepted from the HTTP API and from client events.
     #[inline]
-    pub fn increment_connection_count(&mut self) {
-        self.connection_count += 1;
+    pub fn get_max_event_payload_size(&self) -> usize {
+        self.max_event_payload_size
     }
 
     #[inline]
-    pub fn decrement_connection_count(&mut self) {
-        self.connection_count -= 1;
+    pub fn set_max_event_payload_size(&mut self, max_event_payload_size: usize) {
+        self.max_event_payload_size = max_event_payload_size;
     }
 
     #[inline]
@@ -173,4 +361,63 @@ impl App {
     pub fn is_statistics_enabled(&self) -> bool {
         self.flags & Self::STATISTICS_FLAG != 0
     }
+
+    #[inline]
+    pub fn enable(&mut self, enabled: bool) {
+        if enabled {
+            self.flags &= !Self::DISABLED_FLAG;
+        } else {
+            self.flags |= Self::DISABLED_FLAG;
+        }
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.flags & Self::DISABLED_FLAG == 0
+    }
+
+    #[inline]
+    fn default_max_batch_size() -> usize {
+        Self::DEFAULT_MAX_BATCH_SIZE
+    }
+
+    #[inline]
+    fn default_activity_timeout() -> u64 {
+        Self::DEFAULT_ACTIVITY_TIMEOUT
+    }
+
+    #[inline]
+    fn default_pong_timeout() -> u64 {
+        Self::DEFAULT_PONG_TIMEOUT
+    }
+
+    /// Timeouts of 0 would make the idle timer fire right away, so they're raised to a second.
+    fn deserialize_timeout<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        Ok(u64::deserialize(deserializer)?.max(Self::MIN_TIMEOUT))
+    }
+
+    #[inline]
+    fn default_max_outbound_queue() -> usize {
+        Self::DEFAULT_MAX_OUTBOUND_QUEUE
+    }
+
+    #[inline]
+    fn default_max_queue_overflows() -> u32 {
+        Self::DEFAULT_MAX_QUEUE_OVERFLOWS
+    }
+
+    #[inline]
+    fn default_max_presence_member_size() -> usize {
+        Self::DEFAULT_MAX_PRESENCE_MEMBER_SIZE
+    }
+
+    #[inline]
+    fn default_max_presence_members_per_channel() -> usize {
+        Self::DEFAULT_MAX_PRESENCE_MEMBERS_PER_CHANNEL
+    }
+
+    #[inline]
+    fn default_max_event_payload_size() -> usize {
+        Self::DEFAULT_MAX_EVENT_PAYLOAD_SIZE
+    }
 }
@@ -8,6 +8,11 @@ pub trait AppManager: Send + Sync {
     }
     fn find_by_key(&self, key: &str) -> Option<Arc<App>>;
     fn find_by_secret(&self, secret: &str) -> Option<Arc<App>>;
+    fn get_apps(&self) -> Vec<Arc<App>>;
+    /// Whether the apps are loaded, managers loading them in the background override this.
+    fn is_ready(&self) -> bool {
+        true
+    }
     fn add(&mut self, app: Arc<App>);
     fn update(&mut self, app: Arc<App>);
     fn remove(&mut self, id: &str) -> bool;
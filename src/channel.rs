@@ -4,6 +4,7 @@ use crate::logger::Log;
 use crate::payload::Payload;
 use async_trait::async_trait;
 use hmac::{Hmac, Mac};
+use hyper::body::Bytes;
 use serde_json::{json, Value};
 use sha2::Sha256;
 use std::collections::HashMap;
@@ -66,7 +67,7 @@ pub trait Channel: Send + Sync {
     }
 
     #[inline]
-    async fn save_connection(&mut self, client: Arc<Client>) -> Result<(), FastSocketError> {
+    async fn save_connection(&self, client: Arc<Client>) -> Result<(), FastSocketError> {
         let socket_id = client.get_socket_id().to_string();
         Log::debug(&format!("Saving new connection from {}", socket_id));
 
@@ -97,7 +98,7 @@ pub trait Channel: Send + Sync {
 
     #[inline]
     async fn subscribe(
-        &mut self,
+        &self,
         client: Arc<Client>,
         payload: &Payload,
     ) -> Result<(), FastSocketError> {
@@ -106,7 +107,7 @@ pub trait Channel: Send + Sync {
 
     #[inline]
     async fn default_subscribe(
-        &mut self,
+        &self,
         client: Arc<Client>,
         _payload: &Payload,
     ) -> Result<(), FastSocketError> {
@@ -124,9 +125,7 @@ pub trait Channel: Send + Sync {
         }
 
         Log::debug("Sending subscription succeeded");
-        let socket = client.socket();
-        let mut guard = socket.lock().await;
-        guard.send(&payload?).await?;
+        client.socket().send(&payload?).await?;
 
         Log::debug("Subscription succeeded sent");
 
@@ -134,12 +133,12 @@ pub trait Channel: Send + Sync {
     }
 
     #[inline]
-    async fn unsubscribe(&mut self, socket_id: &str) -> Result<(), FastSocketError> {
+    async fn unsubscribe(&self, socket_id: &str) -> Result<(), FastSocketError> {
         self.default_unsubscribe(socket_id).await
     }
 
     #[inline]
-    async fn default_unsubscribe(&mut self, socket_id: &str) -> Result<(), FastSocketError> {
+    async fn default_unsubscribe(&self, socket_id: &str) -> Result<(), FastSocketError> {
         Log::debug(&format!("Removing connection: {}", socket_id));
         let mut write_guard = self.get_connections().write().await;
         write_guard.remove(socket_id);
@@ -156,26 +155,42 @@ pub trait Channel: Send + Sync {
         clients_count
     }
 
+    /// Number of distinct users, only tracked by presence channels.
+    #[inline]
+    async fn get_user_count(&self) -> Option<u64> {
+        None
+    }
+
+    /// Distinct user ids of the members, only tracked by presence channels.
+    #[inline]
+    async fn get_user_ids(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// User id a socket joined with, only tracked by presence channels.
+    #[inline]
+    async fn get_user_id(&self, _socket_id: &str) -> Option<String> {
+        None
+    }
+
     #[inline]
-    async fn broadcast(&mut self, payload: &Payload) -> Result<(), FastSocketError> {
-        let write_guard = self.get_connections().write().await;
-        for client in write_guard.values() {
-            let socket = client.socket();
-            let mut guard = socket.lock().await;
-            let result = guard.send(payload).await;
-            drop(guard);
+    async fn broadcast(&self, payload: &Payload) -> Result<(), FastSocketError> {
+        let frame = Bytes::from(payload.compile(None)?);
+        let read_guard = self.get_connections().read().await;
+        for client in read_guard.values() {
+            let result = client.socket().send_shared(payload, &frame).await;
 
             if result.is_err() {
                 Log::error(&format!("Failed to send payload: {:?}", result));
             }
         }
-        drop(write_guard);
+        drop(read_guard);
         Ok(())
     }
 
     #[inline]
     async fn broadcast_to_others(
-        &mut self,
+        &self,
         client: Arc<Client>,
         payload: &Payload,
     ) -> Result<(), FastSocketError> {
@@ -185,17 +200,15 @@ pub trait Channel: Send + Sync {
 
     #[inline]
     async fn broadcast_to_everyone_except(
-        &mut self,
+        &self,
         socket_id: &str,
         payload: &Payload,
     ) -> Result<(), FastSocketError> {
-        let write_guard = self.get_connections().write().await;
-        for (id, client) in write_guard.iter() {
+        let frame = Bytes::from(payload.compile(None)?);
+        let read_guard = self.get_connections().read().await;
+        for (id, client) in read_guard.iter() {
             if id != socket_id {
-                let socket = client.socket();
-                let mut guard = socket.lock().await;
-                let result = guard.send(payload).await;
-                drop(guard);
+                let result = client.socket().send_shared(payload, &frame).await;
 
                 if result.is_err() {
                     Log::error(&format!("Failed to send payload: {:?}", result));
@@ -207,9 +220,29 @@ pub trait Channel: Send + Sync {
 
     #[inline]
     async fn to_array(&self) -> Value {
-        json!({
+        let mut attributes = json!({
             "occupied": self.has_connection().await,
             "subscription_count": self.get_clients_count().await,
-        })
+        });
+        if let Some(user_count) = self.get_user_count().await {
+            attributes["user_count"] = Value::from(user_count);
+        }
+        attributes
     }
 }
+
+pub const MAX_CHANNEL_NAME_LENGTH: usize = 164;
+
+/// Checks a channel name against Pusher's rules: up to 164 characters out of
+/// `A-Z a-z 0-9 _ - = @ , . ; #`.
+pub fn validate_channel_name(channel_name: &str) -> Result<(), FastSocketError> {
+    let valid = !channel_name.is_empty()
+        && channel_name.len() <= MAX_CHANNEL_NAME_LENGTH
+        && channel_name.chars().all(|c| c.is_ascii_alphanumeric() || "_-=@,.;#".contains(c));
+
+    if !valid {
+        return Err(FastSocketError::InvalidChannelNameError);
+    }
+
+    Ok(())
+}
@@ -1,24 +1,26 @@
+use crate::channel::validate_channel_name;
 use crate::channel_manager::ChannelManager;
 use crate::client::Client;
 use crate::errors::FastSocketError;
 use crate::logger::Log;
 use crate::message::Message;
 use crate::payload::Payload;
+use crate::request_auth::verify_hmac;
 use async_trait::async_trait;
+use serde_json::Value;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
 pub struct ChannelProtocolMessage {
     client: Arc<Client>,
     payload: Payload,
-    channel_manager: Arc<RwLock<Box<dyn ChannelManager>>>,
+    channel_manager: Arc<Box<dyn ChannelManager>>,
 }
 
 impl ChannelProtocolMessage {
     pub fn new(
         client: Arc<Client>,
         payload: Payload,
-        channel_manager: Arc<RwLock<Box<dyn ChannelManager>>>,
+        channel_manager: Arc<Box<dyn ChannelManager>>,
     ) -> Self {
         Self {
             client,
@@ -26,6 +28,24 @@ impl ChannelProtocolMessage {
             channel_manager,
         }
     }
+
+    /// Tells the client why it couldn't join the channel, errors without a subscription status bubble up.
+    async fn send_subscription_error(&self, channel_name: &str, error: FastSocketError) -> Result<(), FastSocketError> {
+        let Some((error_type, status)) = error.subscription_error() else {
+            return Err(error);
+        };
+        Log::debug(&format!("Subscription to {} failed: {:?}", channel_name, error));
+
+        let payload = Payload::builder()
+            .event("pusher:subscription_error")
+            .channel(channel_name)
+            .add_data("type", error_type)
+            .add_data("error", error.to_string())
+            .add_data("status", status)
+            .build()?;
+
+        self.client.get_socket().send(&payload).await
+    }
 }
 
 #[async_trait]
@@ -35,10 +55,7 @@ impl Message for ChannelProtocolMessage {
         match self.payload.get_event() {
             "pusher:ping" => {
                 Log::debug("Received ping");
-                let socket = self.client.get_socket();
-                let mut guard = socket.lock().await;
-                let result = guard.pong().await;
-                drop(guard);
+                let result = self.client.get_socket().pong().await;
 
                 if result.is_err() {
                     Log::error(&format!("Error sending pong: {:?}", result));
@@ -48,50 +65,82 @@ impl Message for ChannelProtocolMessage {
             }
             "pusher:subscribe" => {
                 Log::debug("Received subscribe");
-                let channel_name = self.payload.get_data_str("channel");
-                if channel_name.is_none() {
-                    Log::error("Invalid channel name");
-                    return Ok(());
+                let channel_name = self.payload.get_data_str("channel").unwrap_or_default();
+                // `#` channels are reserved for the server, e.g. `#server-to-user-*`
+                if validate_channel_name(channel_name).is_err() || channel_name.starts_with('#') {
+                    Log::error(&format!("Invalid channel name: {}", channel_name));
+                    return self.send_subscription_error(channel_name, FastSocketError::InvalidChannelNameError).await;
                 }
-                let channel_name = channel_name.unwrap();
-                let read_guard = self.channel_manager.read().await;
-                let e_channel = read_guard.find(self.client.get_app().get_id(), channel_name);
-                drop(read_guard);
-                let channel = if e_channel.is_some() {
-                    Log::debug(&format!("Found channel: {}", channel_name));
-                    e_channel.unwrap()
-                } else {
-                let mut write_guard = self.channel_manager.write().await;
-                    let channel = write_guard.find_or_create(self.client.get_app().get_id(), channel_name);
-                    drop(write_guard);
-                    Log::debug(&format!("Created channel: {}", channel_name));
-                    channel
-                };
+                let channel = self.channel_manager.find_or_create(self.client.get_app().get_id(), channel_name);
 
                 Log::debug(&format!("Subscribing to channel: {}", channel_name));
-                channel.write().await.subscribe(self.client.clone(), &self.payload).await?;
+                // Tracked up front, so teardown still finds the channel when subscribing fails half way
+                let new_subscription = self.client.add_subscription(channel_name);
+                if let Err(e) = channel.subscribe(self.client.clone(), &self.payload).await {
+                    if new_subscription {
+                        self.client.remove_subscription(channel_name);
+                        if let Err(e) = channel.unsubscribe(self.client.get_socket_id()).await {
+                            Log::error(&format!("Error leaving {} after a failed subscribe: {:?}", channel_name, e));
+                        }
+                    }
+                    return self.send_subscription_error(channel_name, e).await;
+                }
                 Log::debug(&format!("Subscribed to channel: {}", channel_name));
 
                 Ok(())
             }
             "pusher:unsubscribe" => {
                 Log::debug("Received unsubscribe");
-                let channel_name = self.payload.get_channel();
-                let read_guard = self.channel_manager.read().await;
-                let e_channel = read_guard.find(self.client.get_app().get_id(), channel_name);
-                drop(read_guard);
-                if e_channel.is_none() {
+                let channel_name = self.payload.get_data_str("channel")
+                    .unwrap_or(self.payload.get_channel());
+                let channel = self.channel_manager.find(self.client.get_app().get_id(), channel_name);
+                let Some(channel) = channel else {
                     Log::debug(&format!("Channel not found: {}", channel_name));
                     return Ok(());
-                }
-                let channel = e_channel.unwrap();
+                };
 
                 Log::debug(&format!("Unsubscribing from channel: {}", channel_name));
-                channel.write().await.unsubscribe(self.client.get_socket_id()).await?;
+                self.client.remove_subscription(channel_name);
+                channel.unsubscribe(self.client.get_socket_id()).await?;
                 Log::debug(&format!("Unsubscribed from channel: {}", channel_name));
 
                 Ok(())
             }
+            "pusher:signin" => {
+                Log::debug("Received signin");
+                let user_data = self.payload.get_data_str("user_data")
+                    .ok_or(FastSocketError::InvalidMessageError)?;
+                let app = self.client.get_app();
+                let signature = self.payload.get_data_str("auth")
+                    .and_then(|auth| auth.split_once(':'))
+                    .filter(|(key, _)| *key == app.get_key())
+                    .map(|(_, signature)| signature)
+                    .ok_or(FastSocketError::InvalidSignatureError)?;
+
+                let sig_data = format!("{}::user::{}", self.client.get_socket_id(), user_data);
+                verify_hmac(app.get_secret(), &sig_data, signature)?;
+
+                let user: Value = serde_json::from_str(user_data)
+                    .map_err(|_| FastSocketError::InvalidMessageError)?;
+                let user_id = match user.get("id") {
+                    Some(Value::String(id)) if !id.is_empty() => id.clone(),
+                    Some(Value::Number(id)) => id.to_string(),
+                    _ => return Err(FastSocketError::InvalidMessageError),
+                };
+
+                let user_manager = self.client.get_user_manager();
+                let mut write_guard = user_manager.write().await;
+                write_guard.add_socket(app.get_id(), &user_id, self.client.clone());
+                drop(write_guard);
+                Log::debug(&format!("Signed in user: {}", user_id));
+
+                let payload = Payload::builder()
+                    .event("pusher:signin_success")
+                    .add_data("user_data", user_data)
+                    .build()?;
+
+                self.client.get_socket().send(&payload).await
+            }
             &_ => Ok(()),
         }
     }
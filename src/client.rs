@@ -1,16 +1,23 @@
 use crate::app::App;
 use crate::channel_manager::ChannelManager;
+use crate::protocol::ConnectionInfo;
+use crate::user_manager::UserManager;
 use crate::websocket_connection::WebsocketConnection;
-use std::sync::Arc;
-use tokio::sync::{Mutex, RwLock};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{watch, RwLock};
 
 #[derive(Clone)]
 pub struct Client {
     socket_id: String,
     public_key: String,
     app: Arc<App>,
-    ws: Arc<Mutex<WebsocketConnection>>,
-    channel_manager: Arc<RwLock<Box<dyn ChannelManager>>>,
+    connection_info: ConnectionInfo,
+    ws: WebsocketConnection,
+    channel_manager: Arc<Box<dyn ChannelManager>>,
+    user_manager: Arc<RwLock<Box<dyn UserManager>>>,
+    termination: watch::Sender<Option<(u16, String)>>,
+    subscriptions: Arc<Mutex<HashSet<String>>>,
 }
 
 impl Client {
@@ -18,19 +25,25 @@ impl Client {
     pub fn new(
         ws: WebsocketConnection,
         app: Arc<App>,
-        channel_manager: Arc<RwLock<Box<dyn ChannelManager>>>,
+        connection_info: ConnectionInfo,
+        channel_manager: Arc<Box<dyn ChannelManager>>,
+        user_manager: Arc<RwLock<Box<dyn UserManager>>>,
     ) -> Self {
         Self {
             app,
-            ws: Arc::new(Mutex::new(ws)),
+            connection_info,
+            ws,
             socket_id: Self::generate_unique_socket_id(),
             public_key: String::with_capacity(64),
             channel_manager,
+            user_manager,
+            termination: watch::channel(None).0,
+            subscriptions: Arc::new(Mutex::new(HashSet::new())),
         }
     }
 
-    pub fn socket(&self) -> Arc<Mutex<WebsocketConnection>> {
-        self.ws.clone()
+    pub fn socket(&self) -> &WebsocketConnection {
+        &self.ws
     }
 
     #[inline(always)]
@@ -55,9 +68,51 @@ impl Client {
         self.app.clone()
     }
 
+    /// Protocol version and client library announced when connecting.
     #[inline(always)]
-    pub fn get_socket(&self) -> Arc<Mutex<WebsocketConnection>> {
-        self.ws.clone()
+    pub fn get_connection_info(&self) -> &ConnectionInfo {
+        &self.connection_info
+    }
+
+    #[inline(always)]
+    pub fn get_socket(&self) -> &WebsocketConnection {
+        &self.ws
+    }
+
+    #[inline(always)]
+    pub fn get_user_manager(&self) -> Arc<RwLock<Box<dyn UserManager>>> {
+        self.user_manager.clone()
+    }
+
+    /// Asks the connection task to stop reading and close the socket with the given close code.
+    #[inline]
+    pub fn terminate(&self, code: u16, reason: &str) {
+        self.termination.send_replace(Some((code, reason.to_string())));
+    }
+
+    #[inline]
+    pub fn on_terminate(&self) -> watch::Receiver<Option<(u16, String)>> {
+        self.termination.subscribe()
+    }
+
+    /// Remembers a channel the client joined, so teardown only visits those.
+    #[inline]
+    pub fn add_subscription(&self, channel_name: &str) -> bool {
+        let mut guard = self.subscriptions.lock().unwrap_or_else(|e| e.into_inner());
+        guard.insert(channel_name.to_string())
+    }
+
+    #[inline]
+    pub fn remove_subscription(&self, channel_name: &str) -> bool {
+        let mut guard = self.subscriptions.lock().unwrap_or_else(|e| e.into_inner());
+        guard.remove(channel_name)
+    }
+
+    /// Empties the subscription set, handing back the channels it held.
+    #[inline]
+    pub fn take_subscriptions(&self) -> Vec<String> {
+        let mut guard = self.subscriptions.lock().unwrap_or_else(|e| e.into_inner());
+        guard.drain().collect()
     }
 }
 
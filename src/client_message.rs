@@ -6,19 +6,18 @@ use crate::message::Message;
 use crate::payload::Payload;
 use async_trait::async_trait;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
 pub struct ClientMessage {
     client: Arc<Client>,
     payload: Payload,
-    channel_manager: Arc<RwLock<Box<dyn ChannelManager>>>,
+    channel_manager: Arc<Box<dyn ChannelManager>>,
 }
 
 impl ClientMessage {
     pub fn new(
         client: Arc<Client>,
         payload: Payload,
-        channel_manager: Arc<RwLock<Box<dyn ChannelManager>>>,
+        channel_manager: Arc<Box<dyn ChannelManager>>,
     ) -> Self {
         Self {
             client,
@@ -26,13 +25,65 @@ impl ClientMessage {
             channel_manager,
         }
     }
+
+    #[inline]
+    fn validate(&self) -> Result<(), FastSocketError> {
+        if !self.client.get_app().is_client_messages_enabled() {
+            return Err(FastSocketError::ClientEventsDisabledError);
+        }
+
+        if !self.payload.get_event().starts_with("client-") {
+            return Err(FastSocketError::InvalidClientEventError);
+        }
+        Payload::validate_event_name(self.payload.get_event())?;
+
+        if self.payload.get_data_size() > self.client.get_app().get_max_event_payload_size() {
+            return Err(FastSocketError::PayloadTooLargeError);
+        }
+
+        let channel_name = self.payload.get_channel();
+        if channel_name.starts_with("private-encrypted-") {
+            return Err(FastSocketError::InvalidClientEventError);
+        }
+
+        if !channel_name.starts_with("private-") && !channel_name.starts_with("presence-") {
+            return Err(FastSocketError::InvalidClientEventError);
+        }
+
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl Message for ClientMessage {
     async fn respond(&self) -> Result<(), FastSocketError> {
         Log::debug("Received client message");
-        // Implementation here
-        Ok(())
+        self.validate()?;
+
+        let channel_name = self.payload.get_channel();
+        let channel = self.channel_manager.find(self.client.get_app().get_id(), channel_name)
+            .ok_or(FastSocketError::NotSubscribedError)?;
+
+        let subscribed = channel.get_connections()
+            .read()
+            .await
+            .contains_key(self.client.get_socket_id());
+        if !subscribed {
+            return Err(FastSocketError::NotSubscribedError);
+        }
+
+        let mut builder = Payload::builder()
+            .event(self.payload.get_event())
+            .channel(channel_name)
+            .data(self.payload.get_data().clone());
+        if let Some(data) = self.payload.get_raw_data() {
+            builder = builder.raw_data(data);
+        }
+        if let Some(user_id) = channel.get_user_id(self.client.get_socket_id()).await {
+            builder = builder.user_id(user_id);
+        }
+
+        let payload = builder.build()?;
+        channel.broadcast_to_others(self.client.clone(), &payload).await
     }
 }
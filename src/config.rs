@@ -0,0 +1,114 @@
+use crate::errors::FastSocketError;
+use std::env;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+/// How the server spreads connections over threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeMode {
+    /// A single thread runs everything.
+    CurrentThread,
+    /// One work-stealing runtime with `workers` threads behind a single listener.
+    MultiThread,
+    /// `workers` threads, each with its own runtime and `SO_REUSEPORT` listener.
+    ReusePort,
+}
+
+impl FromStr for RuntimeMode {
+    type Err = FastSocketError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "current_thread" => Ok(RuntimeMode::CurrentThread),
+            "multi_thread" => Ok(RuntimeMode::MultiThread),
+            "reuseport" => Ok(RuntimeMode::ReusePort),
+            _ => Err(FastSocketError::InvalidConfigError(format!("unknown runtime: {}", value))),
+        }
+    }
+}
+
+/// Server settings, read from `FASTSOCKET_*` environment variables.
+///
+/// - `FASTSOCKET_HOST` / `FASTSOCKET_PORT`: address to listen on, `127.0.0.1:6002` by default.
+/// - `FASTSOCKET_RUNTIME`: `current_thread` (default), `multi_thread` or `reuseport`.
+/// - `FASTSOCKET_WORKERS`: worker threads, defaults to the number of cores.
+/// - `FASTSOCKET_APPS`: path of the apps file, `apps.json` by default.
+/// - `FASTSOCKET_ADMIN_PORT`: serves health and metrics on their own port instead of the main one.
+/// - `FASTSOCKET_DRAIN_TIMEOUT`: seconds the server keeps running, reported as not ready, after a shutdown signal.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    host: String,
+    port: u16,
+    runtime: RuntimeMode,
+    workers: usize,
+    apps_path: String,
+    admin_port: Option<u16>,
+    drain_timeout: u64,
+}
+
+impl ServerConfig {
+    const DEFAULT_HOST: &'static str = "127.0.0.1";
+    const DEFAULT_PORT: u16 = 6002;
+    const DEFAULT_APPS_PATH: &'static str = "apps.json";
+    const DEFAULT_DRAIN_TIMEOUT: u64 = 5;
+
+    pub fn from_env() -> Result<Self, FastSocketError> {
+        let workers = Self::var("FASTSOCKET_WORKERS")?
+            .unwrap_or_else(|| thread::available_parallelism().map(usize::from).unwrap_or(1));
+        if workers == 0 {
+            return Err(FastSocketError::InvalidConfigError(String::from("FASTSOCKET_WORKERS must be at least 1")));
+        }
+
+        Ok(Self {
+            host: Self::var("FASTSOCKET_HOST")?.unwrap_or_else(|| Self::DEFAULT_HOST.to_string()),
+            port: Self::var("FASTSOCKET_PORT")?.unwrap_or(Self::DEFAULT_PORT),
+            runtime: Self::var("FASTSOCKET_RUNTIME")?.unwrap_or(RuntimeMode::CurrentThread),
+            workers,
+            apps_path: Self::var("FASTSOCKET_APPS")?.unwrap_or_else(|| Self::DEFAULT_APPS_PATH.to_string()),
+            admin_port: Self::var("FASTSOCKET_ADMIN_PORT")?,
+            drain_timeout: Self::var("FASTSOCKET_DRAIN_TIMEOUT")?.unwrap_or(Self::DEFAULT_DRAIN_TIMEOUT),
+        })
+    }
+
+    #[inline]
+    fn var<T: FromStr>(name: &str) -> Result<Option<T>, FastSocketError> {
+        match env::var(name) {
+            Ok(value) if !value.is_empty() => value.parse()
+                .map(Some)
+                .map_err(|_| FastSocketError::InvalidConfigError(format!("invalid {}: {}", name, value))),
+            _ => Ok(None),
+        }
+    }
+
+    #[inline]
+    pub fn get_address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    #[inline]
+    pub fn get_runtime(&self) -> RuntimeMode {
+        self.runtime
+    }
+
+    #[inline]
+    pub fn get_workers(&self) -> usize {
+        self.workers
+    }
+
+    #[inline]
+    pub fn get_apps_path(&self) -> &str {
+        &self.apps_path
+    }
+
+    /// Address of the admin listener, `None` when admin routes share the main listener.
+    #[inline]
+    pub fn get_admin_address(&self) -> Option<String> {
+        self.admin_port.map(|port| format!("{}:{}", self.host, port))
+    }
+
+    #[inline]
+    pub fn get_drain_timeout(&self) -> Duration {
+        Duration::from_secs(self.drain_timeout)
+    }
+}
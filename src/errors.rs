@@ -67,4 +67,118 @@ pub enum FastSocketError {
 
     #[error("Error handling message")]
     ErrorHandlingMessage,
+
+    #[error("Missing authentication parameters")]
+    MissingAuthParamsError,
+
+    #[error("Authentication timestamp expired")]
+    AuthTimestampExpiredError,
+
+    #[error("Invalid body_md5 provided")]
+    InvalidBodyMd5Error,
+
+    #[error("Client events are not enabled for this app")]
+    ClientEventsDisabledError,
+
+    #[error("Client events must be named client-* and sent to private or presence channels")]
+    InvalidClientEventError,
+
+    #[error("Client is not subscribed to the channel")]
+    NotSubscribedError,
+
+    #[error("App is disabled")]
+    AppDisabledError,
+
+    #[error("Connection is unauthorized")]
+    UnauthorizedError,
+
+    #[error("Server asked the client to reconnect")]
+    ReconnectError,
+
+    #[error("Pong reply not received")]
+    PongTimeoutError,
+
+    #[error("Invalid channel_data provided")]
+    InvalidChannelDataError,
+
+    #[error("Presence member data is too large")]
+    MemberDataTooLargeError,
+
+    #[error("Invalid channel name provided")]
+    InvalidChannelNameError,
+
+    #[error("Invalid event name provided")]
+    InvalidEventNameError,
+
+    #[error("Event payload is too large")]
+    PayloadTooLargeError,
+
+    #[error("Presence channel is full")]
+    PresenceMembersLimitError,
+
+    #[error("Unsupported protocol version")]
+    UnsupportedProtocolError,
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfigError(String),
+}
+
+impl FastSocketError {
+    /// Pusher close code for errors that end the connection.
+    ///
+    /// pusher-js picks its reconnect strategy from the code: 4000-4099 never
+    /// reconnect, 4100-4199 back off, 4200-4299 reconnect right away.
+    #[inline]
+    pub fn close_code(&self) -> Option<u16> {
+        match self {
+            FastSocketError::InvalidAppError | FastSocketError::InvalidAppKeyError => Some(4001),
+            FastSocketError::AppDisabledError => Some(4003),
+            FastSocketError::InvalidAppPathError => Some(4005),
+            FastSocketError::UnsupportedProtocolError => Some(4007),
+            FastSocketError::ServerCapacityError => Some(4004),
+            FastSocketError::UnauthorizedError => Some(4009),
+            FastSocketError::ReconnectError => Some(4200),
+            FastSocketError::PongTimeoutError => Some(4201),
+            _ => None,
+        }
+    }
+
+    /// Whether the client should be told about this error with a `pusher:error` frame.
+    #[inline]
+    pub fn is_client_error(&self) -> bool {
+        self.close_code().is_some() || matches!(
+            self,
+            FastSocketError::InvalidSignatureError
+                | FastSocketError::InvalidMessageError
+                | FastSocketError::InvalidPayloadError
+                | FastSocketError::ErrorDecodingPayload
+                | FastSocketError::ClientEventsDisabledError
+                | FastSocketError::InvalidClientEventError
+                | FastSocketError::NotSubscribedError
+                | FastSocketError::InvalidEventNameError
+                | FastSocketError::PayloadTooLargeError
+        )
+    }
+
+    /// Code carried by the `pusher:error` frame, `None` is sent as `null`.
+    #[inline]
+    pub fn error_code(&self) -> Option<u16> {
+        match self {
+            FastSocketError::InvalidSignatureError => Some(4009),
+            _ => self.close_code(),
+        }
+    }
+
+    /// `type` and `status` of the `pusher:subscription_error` sent when a subscription fails with this error.
+    #[inline]
+    pub fn subscription_error(&self) -> Option<(&'static str, u16)> {
+        match self {
+            FastSocketError::InvalidSignatureError => Some(("AuthError", 401)),
+            FastSocketError::InvalidChannelNameError => Some(("InvalidChannel", 400)),
+            FastSocketError::InvalidChannelDataError => Some(("InvalidChannelData", 400)),
+            FastSocketError::MemberDataTooLargeError => Some(("LimitReached", 413)),
+            FastSocketError::PresenceMembersLimitError => Some(("LimitReached", 403)),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,57 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Process state behind the health and readiness endpoints.
+#[derive(Debug)]
+pub struct Health {
+    listeners: usize,
+    accepting: AtomicUsize,
+    draining: AtomicBool,
+}
+
+/// Marks a listener as accepting until dropped.
+#[derive(Debug)]
+pub struct Listening {
+    health: Arc<Health>,
+}
+
+impl Drop for Listening {
+    fn drop(&mut self) {
+        self.health.accepting.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl Health {
+    /// `listeners` is how many public listeners have to accept before the server is ready.
+    pub fn new(listeners: usize) -> Arc<Self> {
+        Arc::new(Self {
+            listeners,
+            accepting: AtomicUsize::new(0),
+            draining: AtomicBool::new(false),
+        })
+    }
+
+    #[inline]
+    pub fn listening(self: &Arc<Self>) -> Listening {
+        self.accepting.fetch_add(1, Ordering::AcqRel);
+        Listening {
+            health: self.clone(),
+        }
+    }
+
+    #[inline]
+    pub fn is_accepting(&self) -> bool {
+        self.accepting.load(Ordering::Acquire) >= self.listeners
+    }
+
+    /// Reports the server as not ready, so the orchestrator stops routing clients to it.
+    #[inline]
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::Release);
+    }
+
+    #[inline]
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Acquire)
+    }
+}
@@ -0,0 +1,441 @@
+use crate::app::App;
+use crate::app_manager::AppManager;
+use crate::channel::{validate_channel_name, Channel};
+use crate::channel_manager::ChannelManager;
+use crate::errors::FastSocketError;
+use crate::logger::Log;
+use crate::payload::Payload;
+use crate::request_auth::{parse_query, RequestAuth};
+use crate::user_manager::UserManager;
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::body::Body;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Method, Request, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+const MAX_TRIGGER_CHANNELS: usize = 100;
+/// Largest request body read, checked before the request is authenticated.
+const MAX_BODY_SIZE: usize = 256 * 1024;
+
+#[derive(Deserialize, Debug)]
+struct TriggerRequest {
+    name: Option<String>,
+    data: Option<Value>,
+    channel: Option<String>,
+    channels: Option<Vec<String>>,
+    socket_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BatchRequest {
+    batch: Vec<BatchEvent>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BatchEvent {
+    channel: Option<String>,
+    name: Option<String>,
+    data: Option<Value>,
+    socket_id: Option<String>,
+    info: Option<String>,
+}
+
+pub struct HttpApi {
+    request_auth: RequestAuth,
+    channel_manager: Arc<Box<dyn ChannelManager>>,
+    user_manager: Arc<RwLock<Box<dyn UserManager>>>,
+}
+
+impl HttpApi {
+    pub fn new(
+        app_manager: Arc<Box<dyn AppManager>>,
+        channel_manager: Arc<Box<dyn ChannelManager>>,
+        user_manager: Arc<RwLock<Box<dyn UserManager>>>,
+    ) -> Arc<Box<Self>> {
+        Arc::new(Box::new(Self {
+            request_auth: RequestAuth::new(app_manager),
+            channel_manager,
+            user_manager,
+        }))
+    }
+
+    /// Answers every request, errors become JSON error responses.
+    pub async fn handle(&self, req: Request<Incoming>) -> Response<Full<Bytes>> {
+        match self.route(req).await {
+            Ok(response) => response,
+            Err(e) => {
+                Log::error(&format!("HTTP API request failed: {:?}", e));
+                let status = match e {
+                    FastSocketError::ErrorReadingPayload => StatusCode::BAD_REQUEST,
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+                Self::error(status, &e.to_string())
+            }
+        }
+    }
+
+    async fn route(&self, req: Request<Incoming>) -> Result<Response<Full<Bytes>>, FastSocketError> {
+        let (parts, body) = req.into_parts();
+        let too_large = || Ok(Self::error(StatusCode::PAYLOAD_TOO_LARGE, "Request body is too large"));
+        if body.size_hint().lower() > MAX_BODY_SIZE as u64 {
+            return too_large();
+        }
+        let body = match Limited::new(body, MAX_BODY_SIZE).collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(e) if e.is::<LengthLimitError>() => return too_large(),
+            Err(_) => return Err(FastSocketError::ErrorReadingPayload),
+        };
+
+        let path = parts.uri.path();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let (app_id, route) = match segments.as_slice() {
+            ["apps", app_id, route @ ..] => (*app_id, route),
+            _ => return Ok(Self::error(StatusCode::NOT_FOUND, "Not found")),
+        };
+
+        let app = match self.request_auth.verify(&parts.method, path, parts.uri.query(), &body) {
+            Ok(app) => app,
+            Err(e) => {
+                Log::error(&format!("HTTP API authentication failed: {:?}", e));
+                return Ok(Self::error(StatusCode::UNAUTHORIZED, &e.to_string()));
+            }
+        };
+
+        if app.get_id() != app_id {
+            return Ok(Self::error(StatusCode::FORBIDDEN, "Key does not belong to this app"));
+        }
+
+        match (&parts.method, route) {
+            (&Method::POST, ["events"]) => self.trigger(&app, &body).await,
+            (&Method::POST, ["batch_events"]) => self.trigger_batch(&app, &body).await,
+            (&Method::GET, ["channels"]) => self.channels(&app, parts.uri.query()).await,
+            (&Method::GET, ["channels", channel_name]) => self.channel(&app, channel_name, parts.uri.query()).await,
+            (&Method::GET, ["channels", channel_name, "users"]) => self.users(&app, channel_name).await,
+            (&Method::POST, ["users", user_id, "terminate_connections"]) => {
+                self.terminate_user_connections(&app, user_id).await
+            }
+            _ => Ok(Self::error(StatusCode::NOT_FOUND, "Not found")),
+        }
+    }
+
+    async fn trigger(&self, app: &Arc<App>, body: &[u8]) -> Result<Response<Full<Bytes>>, FastSocketError> {
+        let request: TriggerRequest = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(_) => return Ok(Self::error(StatusCode::BAD_REQUEST, "Invalid JSON body")),
+        };
+
+        let name = match request.name {
+            Some(name) if !name.is_empty() => name,
+            _ => return Ok(Self::error(StatusCode::BAD_REQUEST, "Missing event name")),
+        };
+
+        let data = match Self::event_data(request.data) {
+            Some(data) => data,
+            None => return Ok(Self::error(StatusCode::BAD_REQUEST, "Missing event data")),
+        };
+
+        let channels = match (request.channel, request.channels) {
+            (_, Some(channels)) if !channels.is_empty() => channels,
+            (Some(channel), _) if !channel.is_empty() => vec![channel],
+            _ => return Ok(Self::error(StatusCode::BAD_REQUEST, "Missing channel")),
+        };
+
+        if channels.len() > MAX_TRIGGER_CHANNELS {
+            return Ok(Self::error(StatusCode::BAD_REQUEST, "Too many channels"));
+        }
+
+        if let Some(response) = Self::validate_event(app, &name, &channels, &data) {
+            return Ok(response);
+        }
+
+        for channel_name in channels.iter() {
+            let payload = Payload::builder()
+                .event(name.as_str())
+                .channel(channel_name.as_str())
+                .raw_data(data.as_str())
+                .build()?;
+            self.publish(app, &payload, request.socket_id.as_deref()).await?;
+        }
+
+        Ok(Self::json(StatusCode::OK, json!({})))
+    }
+
+    async fn trigger_batch(&self, app: &Arc<App>, body: &[u8]) -> Result<Response<Full<Bytes>>, FastSocketError> {
+        let request: BatchRequest = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(_) => return Ok(Self::error(StatusCode::BAD_REQUEST, "Invalid JSON body")),
+        };
+
+        if request.batch.len() > app.get_max_batch_size() {
+            return Ok(Self::error(
+                StatusCode::BAD_REQUEST,
+                &format!("Batch too large, maximum is {} events", app.get_max_batch_size()),
+            ));
+        }
+
+        // Validate every event up front so a bad entry doesn't leave the batch half published
+        let mut payloads = Vec::with_capacity(request.batch.len());
+        for (index, event) in request.batch.into_iter().enumerate() {
+            let channel = match event.channel {
+                Some(channel) if !channel.is_empty() => channel,
+                _ => return Ok(Self::error(StatusCode::BAD_REQUEST, &format!("Missing channel in event {}", index))),
+            };
+
+            let data = match Self::event_data(event.data) {
+                Some(data) => data,
+                None => return Ok(Self::error(StatusCode::BAD_REQUEST, &format!("Missing data in event {}", index))),
+            };
+
+            let name = match event.name {
+                Some(name) if !name.is_empty() => name,
+                _ => return Ok(Self::error(StatusCode::BAD_REQUEST, &format!("Missing event name in event {}", index))),
+            };
+
+            if let Some(response) = Self::validate_event(app, &name, std::slice::from_ref(&channel), &data) {
+                return Ok(response);
+            }
+
+            let builder = Payload::builder()
+                .event(name)
+                .channel(channel)
+                .raw_data(data);
+
+            match builder.build() {
+                Ok(payload) => payloads.push((payload, event.socket_id, event.info)),
+                Err(e) => return Ok(Self::error(StatusCode::BAD_REQUEST, &format!("{} in event {}", e, index))),
+            }
+        }
+
+        for (payload, socket_id, _) in payloads.iter() {
+            self.publish(app, payload, socket_id.as_deref()).await?;
+        }
+
+        if payloads.iter().all(|(_, _, info)| info.is_none()) {
+            return Ok(Self::json(StatusCode::OK, json!({})));
+        }
+
+        let mut batch = Vec::with_capacity(payloads.len());
+        for (payload, _, info) in payloads.iter() {
+            let attributes = match info {
+                Some(info) => {
+                    let channel = self.find_channel(app, payload.get_channel()).await;
+                    Self::channel_info(channel.as_ref(), payload.get_channel(), info).await
+                }
+                None => Map::new(),
+            };
+            batch.push(Value::Object(attributes));
+        }
+
+        Ok(Self::json(StatusCode::OK, json!({ "batch": batch })))
+    }
+
+    async fn channels(&self, app: &Arc<App>, query: Option<&str>) -> Result<Response<Full<Bytes>>, FastSocketError> {
+        let params = parse_query(query);
+        let prefix = params.get("filter_by_prefix").map(String::as_str).unwrap_or_default();
+        let info = params.get("info").map(String::as_str).unwrap_or_default();
+
+        if Self::requests_user_count(info) && !prefix.starts_with("presence-") {
+            return Ok(Self::error(
+                StatusCode::BAD_REQUEST,
+                "user_count may only be requested for presence channels",
+            ));
+        }
+
+        let app_channels = self.channel_manager.get_channels(app.get_id());
+
+        let mut channels = Map::new();
+        for channel in app_channels.iter() {
+            let name = channel.get_name();
+            if !name.starts_with(prefix) || !channel.has_connection().await {
+                continue;
+            }
+
+            let attributes = Self::channel_info(Some(channel), name, info).await;
+            channels.insert(name.to_string(), Value::Object(attributes));
+        }
+
+        Ok(Self::json(StatusCode::OK, json!({ "channels": channels })))
+    }
+
+    async fn channel(
+        &self,
+        app: &Arc<App>,
+        channel_name: &str,
+        query: Option<&str>,
+    ) -> Result<Response<Full<Bytes>>, FastSocketError> {
+        let params = parse_query(query);
+        let info = params.get("info").map(String::as_str).unwrap_or_default();
+
+        if Self::requests_user_count(info) && !channel_name.starts_with("presence-") {
+            return Ok(Self::error(
+                StatusCode::BAD_REQUEST,
+                "user_count may only be requested for presence channels",
+            ));
+        }
+
+        let channel = self.find_channel(app, channel_name).await;
+        let occupied = match channel.as_ref() {
+            Some(channel) => channel.has_connection().await,
+            None => false,
+        };
+
+        let mut attributes = Map::new();
+        attributes.insert(String::from("occupied"), Value::from(occupied));
+        attributes.extend(Self::channel_info(channel.as_ref(), channel_name, info).await);
+
+        Ok(Self::json(StatusCode::OK, Value::Object(attributes)))
+    }
+
+    async fn users(&self, app: &Arc<App>, channel_name: &str) -> Result<Response<Full<Bytes>>, FastSocketError> {
+        if !channel_name.starts_with("presence-") {
+            return Ok(Self::error(
+                StatusCode::BAD_REQUEST,
+                "Users may only be requested for presence channels",
+            ));
+        }
+
+        let user_ids = match self.find_channel(app, channel_name).await {
+            Some(channel) => channel.get_user_ids().await,
+            None => Vec::new(),
+        };
+
+        let users: Vec<Value> = user_ids.into_iter()
+            .map(|id| json!({ "id": id }))
+            .collect();
+
+        Ok(Self::json(StatusCode::OK, json!({ "users": users })))
+    }
+
+    async fn terminate_user_connections(
+        &self,
+        app: &Arc<App>,
+        user_id: &str,
+    ) -> Result<Response<Full<Bytes>>, FastSocketError> {
+        let read_guard = self.user_manager.read().await;
+        let clients = read_guard.get_sockets(app.get_id(), user_id);
+        drop(read_guard);
+
+        Log::debug(&format!("Terminating {} connections of user {}", clients.len(), user_id));
+
+        let close_code = FastSocketError::UnauthorizedError.close_code().unwrap_or_default();
+        for client in clients.iter() {
+            client.terminate(close_code, "You got disconnected by the app");
+
+            self.channel_manager.remove_from_all_channels(client.clone()).await;
+
+            let mut write_guard = self.user_manager.write().await;
+            write_guard.remove_socket(app.get_id(), client.get_socket_id());
+            drop(write_guard);
+        }
+
+        Ok(Self::json(StatusCode::OK, json!({})))
+    }
+
+    async fn publish(
+        &self,
+        app: &Arc<App>,
+        payload: &Payload,
+        socket_id: Option<&str>,
+    ) -> Result<(), FastSocketError> {
+        let channel = match self.find_channel(app, payload.get_channel()).await {
+            Some(channel) => channel,
+            None => {
+                Log::debug(&format!("No subscribers on channel: {}", payload.get_channel()));
+                return Ok(());
+            }
+        };
+
+        match socket_id {
+            Some(socket_id) => channel.broadcast_to_everyone_except(socket_id, payload).await,
+            None => channel.broadcast(payload).await,
+        }
+    }
+
+    async fn channel_info(
+        channel: Option<&Arc<Box<dyn Channel>>>,
+        channel_name: &str,
+        info: &str,
+    ) -> Map<String, Value> {
+        let mut attributes = Map::new();
+
+        for attribute in info.split(',').map(str::trim) {
+            match attribute {
+                "subscription_count" => {
+                    let count = match channel {
+                        Some(channel) => channel.get_clients_count().await,
+                        None => 0,
+                    };
+                    attributes.insert(attribute.to_string(), Value::from(count));
+                }
+                "user_count" if channel_name.starts_with("presence-") => {
+                    let count = match channel {
+                        Some(channel) => channel.get_user_count().await.unwrap_or(0),
+                        None => 0,
+                    };
+                    attributes.insert(attribute.to_string(), Value::from(count));
+                }
+                _ => {}
+            }
+        }
+
+        attributes
+    }
+
+    /// Checks a published event against the naming rules and the app's payload limit.
+    #[inline]
+    fn validate_event(app: &App, name: &str, channels: &[String], data: &str) -> Option<Response<Full<Bytes>>> {
+        if Payload::validate_event_name(name).is_err() {
+            return Some(Self::error(StatusCode::BAD_REQUEST, &format!("Invalid event name: {}", name)));
+        }
+
+        if let Some(channel) = channels.iter().find(|channel| validate_channel_name(channel).is_err()) {
+            return Some(Self::error(StatusCode::BAD_REQUEST, &format!("Invalid channel name: {}", channel)));
+        }
+
+        if data.len() > app.get_max_event_payload_size() {
+            return Some(Self::error(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                &format!("Event data too large, maximum is {} bytes", app.get_max_event_payload_size()),
+            ));
+        }
+
+        None
+    }
+
+    #[inline]
+    fn requests_user_count(info: &str) -> bool {
+        info.split(',').any(|attribute| attribute.trim() == "user_count")
+    }
+
+    #[inline]
+    async fn find_channel(&self, app: &Arc<App>, channel_name: &str) -> Option<Arc<Box<dyn Channel>>> {
+        self.channel_manager.find(app.get_id(), channel_name)
+    }
+
+    /// Pusher sends data as a string, but accept any JSON and stringify it.
+    #[inline]
+    fn event_data(data: Option<Value>) -> Option<String> {
+        match data {
+            Some(Value::String(data)) => Some(data),
+            Some(Value::Null) | None => None,
+            Some(data) => Some(data.to_string()),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn json(status: StatusCode, body: Value) -> Response<Full<Bytes>> {
+        let mut response = Response::new(Full::new(Bytes::from(body.to_string())));
+        *response.status_mut() = status;
+        response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        response
+    }
+
+    #[inline]
+    pub(crate) fn error(status: StatusCode, message: &str) -> Response<Full<Bytes>> {
+        Self::json(status, json!({ "error": message }))
+    }
+}
@@ -24,7 +24,7 @@ impl JsonAppManager {
     #[inline]
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Arc<Box<dyn AppManager>>, Box<dyn std::error::Error>> {
         let path = path.as_ref().to_path_buf();
-        let (mut apps, indices) = if path.exists() {
+        let (mut apps, mut indices) = if path.exists() {
             let content = std::fs::read_to_string(&path)?;
             let app_vec: Vec<App> = serde_json::from_str(&content)?;
             let mut indices = Indices::default();
@@ -42,7 +42,7 @@ impl JsonAppManager {
             (HashMap::with_capacity(16), Indices::default())
         };
 
-        apps.insert("fastsocket".to_string(), App::new(
+        let default_app = App::new(
             "fastsocket".to_string(),
             "fastsocket".to_string(),
             "secret".to_string(),
@@ -51,7 +51,10 @@ impl JsonAppManager {
             "/app/".to_string(),
             100,
             0,
-        ).unwrap());
+        ).unwrap();
+        indices.by_key.insert(default_app.get_key().to_string(), default_app.get_id().to_string());
+        indices.by_secret.insert(default_app.get_secret().to_string(), default_app.get_id().to_string());
+        apps.insert(default_app.get_id().to_string(), default_app);
 
         Ok(Arc::new(Box::new(JsonAppManager {
             path,
@@ -81,29 +84,22 @@ impl JsonAppManager {
 impl AppManager for JsonAppManager {
     #[inline]
     fn find(&self, id: &str) -> Option<Arc<App>> {
-        let app = self.apps.get(id);
-        if app.is_none() {
-            return None;
-        }
-        Some(app.unwrap().clone())
+        self.apps.get(id).cloned()
     }
 
     #[inline]
     fn find_by_key(&self, key: &str) -> Option<Arc<App>> {
-        let app = self.indices.by_key.get(key).and_then(|id| self.apps.get(id));
-        if app.is_none() {
-            return None;
-        }
-        Some(app.unwrap().clone())
+        self.indices.by_key.get(key).and_then(|id| self.apps.get(id)).cloned()
     }
 
     #[inline]
     fn find_by_secret(&self, secret: &str) -> Option<Arc<App>> {
-        let app = self.indices.by_secret.get(secret).and_then(|id| self.apps.get(id));
-        if app.is_none() {
-            return None;
-        }
-        Some(app.unwrap().clone())
+        self.indices.by_secret.get(secret).and_then(|id| self.apps.get(id)).cloned()
+    }
+
+    #[inline]
+    fn get_apps(&self) -> Vec<Arc<App>> {
+        self.apps.values().cloned().collect()
     }
 
     #[inline]
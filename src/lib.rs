@@ -1,8 +1,10 @@
 pub mod app;
+pub mod config;
 pub mod app_manager;
 pub mod errors;
 pub mod json_app_manager;
 pub mod client;
+pub mod protocol;
 pub mod payload;
 pub mod channel;
 pub mod public_channel;
@@ -15,6 +17,13 @@ pub mod client_message;
 pub mod message_factory;
 pub mod websocket;
 pub mod websocket_connection;
+pub mod outbound_queue;
 pub mod private_channel;
 pub mod presence_channel;
 pub mod encrypted_channel;
+pub mod http_api;
+pub mod health;
+pub mod router;
+pub mod request_auth;
+pub mod user_manager;
+pub mod local_user_manager;
@@ -2,53 +2,59 @@ use crate::channel::Channel;
 use crate::channel_manager::ChannelManager;
 use crate::client::Client;
 use async_trait::async_trait;
-use std::collections::HashMap;
+use dashmap::DashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
+/// Channels grouped by app, both levels sharded so lookups only lock a single shard.
 pub struct LocalChannelManager {
-    channels: HashMap<String, HashMap<String, Arc<RwLock<Box<dyn Channel>>>>>,
+    channels: DashMap<String, DashMap<String, Arc<Box<dyn Channel>>>>,
 }
 
 impl LocalChannelManager {
     #[inline]
-    pub fn new() -> Arc<RwLock<Box<dyn ChannelManager>>> {
-        Arc::new(RwLock::new(Box::new(Self {
-            channels: HashMap::with_capacity(16),
-        })))
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> Arc<Box<dyn ChannelManager>> {
+        Arc::new(Box::new(Self {
+            channels: DashMap::with_capacity(16),
+        }))
     }
 }
 
 #[async_trait]
 impl ChannelManager for LocalChannelManager {
-    fn create(&mut self, app_id: &str, channel_name: &str) -> Arc<RwLock<Box<dyn Channel>>> {
-        if let Some(channel) = {
-            let app_channels = self.channels
-                .entry(app_id.to_string())
-                .or_insert_with(HashMap::new);
-            app_channels.get(channel_name).cloned()
-        } {
-            return channel;
-        }
+    fn create(&self, app_id: &str, channel_name: &str) -> Arc<Box<dyn Channel>> {
+        let app_channels = self.channels
+            .entry(app_id.to_string())
+            .or_default()
+            .downgrade();
 
-        let channel: Arc<RwLock<Box<(dyn Channel)>>> = self.make_channel(channel_name);
-        self.channels
-            .get_mut(app_id)
-            .unwrap()
-            .insert(channel_name.to_string(), channel.clone());
+        let channel = app_channels
+            .entry(channel_name.to_string())
+            .or_insert_with(|| self.make_channel(channel_name))
+            .clone();
         channel
     }
 
     #[inline]
-    fn get_channels(&self) -> &HashMap<String, HashMap<String, Arc<RwLock<Box<dyn Channel>>>>> {
-        &self.channels
+    fn find(&self, app_id: &str, channel_name: &str) -> Option<Arc<Box<dyn Channel>>> {
+        self.channels
+            .get(app_id)
+            .and_then(|channels| channels.get(channel_name).map(|channel| channel.clone()))
+    }
+
+    #[inline]
+    fn get_channels(&self, app_id: &str) -> Vec<Arc<Box<dyn Channel>>> {
+        self.channels
+            .get(app_id)
+            .map(|channels| channels.iter().map(|channel| channel.clone()).collect())
+            .unwrap_or_default()
     }
 
     #[inline]
-    async fn remove_from_all_channels(&mut self, client: Arc<Client>) {
-        for app_channels in self.channels.values_mut() {
-            for channel in app_channels.values() {
-                let mut channel = channel.write().await;
+    async fn remove_from_all_channels(&self, client: Arc<Client>) {
+        let app_id = client.get_app().get_id().to_string();
+        for channel_name in client.take_subscriptions() {
+            if let Some(channel) = self.find(&app_id, &channel_name) {
                 let _ = channel.unsubscribe(client.get_socket_id()).await;
             }
         }
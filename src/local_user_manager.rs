@@ -0,0 +1,70 @@
+use crate::client::Client;
+use crate::user_manager::UserManager;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+#[derive(Default)]
+struct AppUsers {
+    sockets_by_user: HashMap<String, HashMap<String, Arc<Client>>>,
+    users_by_socket: HashMap<String, HashSet<String>>,
+}
+
+pub struct LocalUserManager {
+    apps: HashMap<String, AppUsers>,
+}
+
+impl LocalUserManager {
+    #[inline]
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> Arc<RwLock<Box<dyn UserManager>>> {
+        Arc::new(RwLock::new(Box::new(Self {
+            apps: HashMap::with_capacity(16),
+        })))
+    }
+}
+
+impl UserManager for LocalUserManager {
+    #[inline]
+    fn add_socket(&mut self, app_id: &str, user_id: &str, client: Arc<Client>) {
+        let users = self.apps.entry(app_id.to_string()).or_default();
+        let socket_id = client.get_socket_id().to_string();
+
+        users.users_by_socket
+            .entry(socket_id.clone())
+            .or_default()
+            .insert(user_id.to_string());
+        users.sockets_by_user
+            .entry(user_id.to_string())
+            .or_default()
+            .insert(socket_id, client);
+    }
+
+    #[inline]
+    fn get_sockets(&self, app_id: &str, user_id: &str) -> Vec<Arc<Client>> {
+        self.apps.get(app_id)
+            .and_then(|users| users.sockets_by_user.get(user_id))
+            .map(|sockets| sockets.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    #[inline]
+    fn remove_socket(&mut self, app_id: &str, socket_id: &str) {
+        let Some(users) = self.apps.get_mut(app_id) else {
+            return;
+        };
+
+        let Some(user_ids) = users.users_by_socket.remove(socket_id) else {
+            return;
+        };
+
+        for user_id in user_ids.iter() {
+            if let Some(sockets) = users.sockets_by_user.get_mut(user_id) {
+                sockets.remove(socket_id);
+                if sockets.is_empty() {
+                    users.sockets_by_user.remove(user_id);
+                }
+            }
+        }
+    }
+}
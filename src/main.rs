@@ -1,83 +1,218 @@
 use std::sync::Arc;
 use fastsocket::json_app_manager::JsonAppManager;
 use fastsocket::local_channel_manager::LocalChannelManager;
+use fastsocket::local_user_manager::LocalUserManager;
 use fastsocket::websocket::WebSocket;
-use fastwebsockets::{upgrade, WebSocketError};
-use http_body_util::Empty;
-use hyper::body::{Bytes, Incoming};
+use fastsocket::http_api::HttpApi;
+use fastsocket::router::{Router, Routes};
+use fastwebsockets::WebSocketError;
+use hyper::body::Incoming;
 use hyper::service::service_fn;
-use hyper::{Request, Response};
-use tokio;
+use hyper::Request;
 use tokio::net::TcpListener;
-use fastsocket::app_manager::AppManager;
-use fastsocket::errors::FastSocketError;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::ToSocketAddrs;
+use std::thread;
+use fastsocket::config::{RuntimeMode, ServerConfig};
+use fastsocket::health::Health;
 use fastsocket::logger::Log;
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
 
-async fn server_upgrade(ws: Arc<Box<WebSocket>>, app_manager: Arc<Box<dyn AppManager>>, mut req: Request<Incoming>) -> Result<Response<Empty<Bytes>>, FastSocketError> {
-    let (response, fut) =
-        upgrade::upgrade(&mut req).map_err(|_| FastSocketError::UpgradeFailedError)?;
-
-    tokio::task::spawn(async move {
-        let path = req.uri().path().to_string();
-        let app_id = path.split('/').nth(2);
-        if app_id.is_none() {
-            Log::error("Invalid path");
-            // return Err(FastSocketError::InvalidAppPathError);
-            return;
-        }
-        let app_id = app_id.unwrap();
+/// How long disconnected clients get to receive their close frames before the process exits.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
 
-        let app = app_manager.find(app_id);
-        if app.is_none() {
-            Log::error(&format!("App not found: {}", app_id));
-            // return Err(FastSocketError::InvalidAppError);
-            return;
-        }
-        let app = app.unwrap();
-        let handle_future = ws.handle_client(fut, app);
-        let pinned_future = Box::pin(handle_future);
-        if let Err(e) = tokio::task::unconstrained(pinned_future).await {
-            eprintln!("Error handling client: {:?}", e);
-        }
-    });
+async fn serve(listener: TcpListener, router: Arc<Box<Router>>) -> Result<(), WebSocketError> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        println!("New connection from {}", stream.peer_addr()?);
+        let router = router.clone();
+        tokio::spawn(async move {
+            let io = hyper_util::rt::TokioIo::new(stream);
+            let conn_fut = hyper::server::conn::http1::Builder::new()
+                .serve_connection(
+                    io,
+                    service_fn(|req: Request<Incoming>| {
+                        let router = router.clone();
+                        async move { Ok::<_, Infallible>(router.handle(req).await) }
+                    }),
+                )
+                .with_upgrades();
+            if let Err(e) = conn_fut.await {
+                println!("Connection error: {:?}", e);
+            }
+        });
+    }
+}
 
-    Ok(response)
+/// Runs the admin routes on their own thread so they stay reachable however busy the workers are.
+fn spawn_admin(address: String, router: Arc<Box<Router>>) -> std::io::Result<thread::JoinHandle<Result<(), WebSocketError>>> {
+    thread::Builder::new()
+        .name(String::from("fastsocket-admin"))
+        .spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_io()
+                .enable_time()
+                .build()?;
+
+            rt.block_on(async move {
+                let listener = TcpListener::bind(&address).await?;
+                println!("Admin listening on {}", address);
+                serve(listener, router).await
+            })
+        })
 }
 
-fn main() -> Result<(), WebSocketError> {
-    let rt = tokio::runtime::Builder::new_current_thread()
-        .enable_io()
-        .build()?;
-
-    rt.block_on(async move {
-        let listener = TcpListener::bind("127.0.0.1:6002").await?;
-        println!("Listening on 127.0.0.1:6002");
-
-        let app_manager = JsonAppManager::new("apps.json").unwrap();
-        let channel_manager = LocalChannelManager::new();
-        let websocket = WebSocket::new(app_manager.clone(), channel_manager.clone());
-
-        loop {
-            let (stream, _) = listener.accept().await?;
-            println!("New connection from {}", stream.peer_addr()?);
-            let ws = websocket.clone();
-            let apm = app_manager.clone();
-            tokio::spawn(async move {
-                let io = hyper_util::rt::TokioIo::new(stream);
-                let conn_fut = hyper::server::conn::http1::Builder::new()
-                    .serve_connection(
-                        io,
-                        service_fn(|req: Request<Incoming>| {
-                            let wsc = ws.clone();
-                            let apmc = apm.clone();
-                            async move { server_upgrade(wsc, apmc, req).await }
-                        }),
-                    )
-                    .with_upgrades();
-                if let Err(e) = conn_fut.await {
-                    println!("Connection error: {:?}", e);
+/// Waits for `SIGTERM` or `SIGINT`, then reports the server as draining for `drain_timeout`
+/// and disconnects the remaining clients before exiting.
+fn spawn_shutdown(
+    health: Arc<Health>,
+    websocket: Arc<Box<WebSocket>>,
+    drain_timeout: Duration,
+) -> std::io::Result<thread::JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("fastsocket-shutdown"))
+        .spawn(move || {
+            let rt = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(rt) => rt,
+                Err(e) => {
+                    Log::error(&format!("Failed to start the shutdown handler: {:?}", e));
+                    return;
+                }
+            };
+
+            rt.block_on(async move {
+                let mut terminate = match signal(SignalKind::terminate()) {
+                    Ok(terminate) => terminate,
+                    Err(e) => {
+                        Log::error(&format!("Failed to listen for SIGTERM: {:?}", e));
+                        return;
+                    }
+                };
+                tokio::select! {
+                    _ = terminate.recv() => {},
+                    _ = tokio::signal::ctrl_c() => {},
                 }
+
+                println!("Draining for {:?} before shutting down", drain_timeout);
+                health.start_draining();
+                tokio::time::sleep(drain_timeout).await;
+
+                websocket.shutdown();
+                let deadline = tokio::time::Instant::now() + CLOSE_TIMEOUT;
+                while websocket.get_connection_count() > 0 && tokio::time::Instant::now() < deadline {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+                std::process::exit(0);
             });
+        })
+}
+
+/// Binds a listener that shares its port with the other workers through `SO_REUSEPORT`.
+fn reuseport_listener(address: &str) -> std::io::Result<std::net::TcpListener> {
+    let address = address.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "no address to bind"))?;
+
+    let socket = Socket::new(Domain::for_address(address), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(1024)?;
+
+    Ok(socket.into())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = ServerConfig::from_env()?;
+    let address = config.get_address();
+
+    let app_manager = JsonAppManager::new(config.get_apps_path())?;
+    let channel_manager = LocalChannelManager::new();
+    let user_manager = LocalUserManager::new();
+    let websocket = WebSocket::new(app_manager.clone(), channel_manager.clone(), user_manager.clone());
+    let http_api = HttpApi::new(app_manager.clone(), channel_manager.clone(), user_manager.clone());
+
+    let listeners = if config.get_runtime() == RuntimeMode::ReusePort { config.get_workers() } else { 1 };
+    let health = Health::new(listeners);
+    spawn_shutdown(health.clone(), websocket.clone(), config.get_drain_timeout())?;
+
+    let admin_address = config.get_admin_address();
+    let routes = if admin_address.is_some() { Routes::Public } else { Routes::All };
+    let router = Router::new(
+        routes,
+        websocket.clone(),
+        app_manager.clone(),
+        channel_manager.clone(),
+        http_api.clone(),
+        health.clone(),
+    );
+    let admin = match admin_address {
+        Some(admin_address) => {
+            let admin_router = Router::new(Routes::Admin, websocket, app_manager, channel_manager, http_api, health.clone());
+            Some(spawn_admin(admin_address, admin_router)?)
+        }
+        None => None,
+    };
+
+    match config.get_runtime() {
+        RuntimeMode::CurrentThread | RuntimeMode::MultiThread => {
+            let rt = if config.get_runtime() == RuntimeMode::MultiThread {
+                tokio::runtime::Builder::new_multi_thread()
+                    .worker_threads(config.get_workers())
+                    .enable_io()
+                    .enable_time()
+                    .build()?
+            } else {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_io()
+                    .enable_time()
+                    .build()?
+            };
+
+            rt.block_on(async move {
+                let listener = TcpListener::bind(&address).await?;
+                println!("Listening on {} ({:?})", address, config.get_runtime());
+
+                let _listening = health.listening();
+                serve(listener, router).await
+            })?;
         }
-    })
+        RuntimeMode::ReusePort => {
+            let mut workers = Vec::with_capacity(config.get_workers());
+            for worker in 0..config.get_workers() {
+                let listener = reuseport_listener(&address)?;
+                let router = router.clone();
+                let health = health.clone();
+
+                workers.push(thread::Builder::new()
+                    .name(format!("fastsocket-worker-{}", worker))
+                    .spawn(move || -> Result<(), WebSocketError> {
+                        let rt = tokio::runtime::Builder::new_current_thread()
+                            .enable_io()
+                            .enable_time()
+                            .build()?;
+
+                        rt.block_on(async move {
+                            let listener = TcpListener::from_std(listener)?;
+                            let _listening = health.listening();
+                            serve(listener, router).await
+                        })
+                    })?);
+            }
+            println!("Listening on {} ({} reuseport workers)", address, workers.len());
+
+            for worker in workers {
+                worker.join().map_err(|_| "worker thread panicked")??;
+            }
+        }
+    }
+
+    if let Some(admin) = admin {
+        admin.join().map_err(|_| "admin thread panicked")??;
+    }
+
+    Ok(())
 }
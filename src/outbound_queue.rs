@@ -0,0 +1,339 @@
+use crate::app::{App, AppState};
+use crate::errors::FastSocketError;
+use fastwebsockets::OpCode;
+use hyper::body::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Frames waiting in a connection's outbound queue.
+#[derive(Debug)]
+pub enum Outbound {
+    Text(Bytes),
+    /// The server's `pusher:ping`, exempt from the capacity so a full queue can't fail the keepalive.
+    Ping(Bytes),
+    Control(OpCode, Vec<u8>),
+    Close(u16, String),
+}
+
+/// What to do with a message when a client's outbound queue is full.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SlowConsumerPolicy {
+    #[default]
+    DropOldest,
+    DropNewest,
+    Disconnect,
+}
+
+#[derive(Default)]
+struct QueueState {
+    frames: VecDeque<Outbound>,
+    messages: usize,
+    overflows: u32,
+    closed: bool,
+}
+
+/// Bounded queue between the senders of a connection and its writer task.
+///
+/// Only text messages count towards the capacity, control and close frames
+/// are always queued so the protocol keeps working for slow clients too.
+/// Pongs are coalesced, so a client pinging without reading can't grow the queue.
+pub struct OutboundQueue {
+    state: Mutex<QueueState>,
+    notify: Notify,
+    policy: SlowConsumerPolicy,
+    capacity: usize,
+    max_overflows: u32,
+    dropped: AtomicU64,
+    app_state: Arc<AppState>,
+}
+
+impl OutboundQueue {
+    const DEFAULT_CAPACITY: usize = 256;
+    const DEFAULT_MAX_OVERFLOWS: u32 = 3;
+
+    #[inline]
+    pub fn new(policy: SlowConsumerPolicy, capacity: usize, max_overflows: u32) -> Self {
+        Self {
+            state: Mutex::new(QueueState::default()),
+            notify: Notify::new(),
+            policy,
+            capacity: capacity.max(1),
+            max_overflows: max_overflows.max(1),
+            dropped: AtomicU64::new(0),
+            app_state: Arc::default(),
+        }
+    }
+
+    /// A queue whose drops also count towards the app's dropped messages.
+    #[inline]
+    pub fn for_app(app: &App) -> Self {
+        Self {
+            app_state: app.get_state().clone(),
+            ..Self::new(
+                app.get_slow_consumer_policy(),
+                app.get_max_outbound_queue(),
+                app.get_max_queue_overflows(),
+            )
+        }
+    }
+
+    /// Queues a frame, applying the slow consumer policy when the queue is full.
+    ///
+    /// Fails with `ReconnectError` once a `Disconnect` queue overflowed too often.
+    pub fn push(&self, message: Outbound) -> Result<(), FastSocketError> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.closed {
+            return Err(FastSocketError::ConnectionClosed);
+        }
+
+        match message {
+            Outbound::Text(_) if state.messages >= self.capacity => {
+                self.record_dropped(1);
+                match self.policy {
+                    SlowConsumerPolicy::DropNewest => return Ok(()),
+                    SlowConsumerPolicy::DropOldest => {
+                        let oldest = state.frames.iter()
+                            .position(|frame| matches!(frame, Outbound::Text(_)));
+                        if let Some(index) = oldest {
+                            state.frames.remove(index);
+                        }
+                        state.frames.push_back(message);
+                    }
+                    SlowConsumerPolicy::Disconnect => {
+                        state.overflows += 1;
+                        if state.overflows >= self.max_overflows {
+                            return Err(FastSocketError::ReconnectError);
+                        }
+                        return Ok(());
+                    }
+                }
+            }
+            Outbound::Text(_) => {
+                state.messages += 1;
+                state.frames.push_back(message);
+            }
+            Outbound::Close(..) => {
+                state.closed = true;
+                state.frames.push_back(message);
+            }
+            Outbound::Control(OpCode::Pong, payload) => {
+                // Answering the latest ping is enough, the pending pong takes its payload
+                let pending = state.frames.iter_mut().find_map(|frame| match frame {
+                    Outbound::Control(OpCode::Pong, pending) => Some(pending),
+                    _ => None,
+                });
+                match pending {
+                    Some(pending) => *pending = payload,
+                    None => state.frames.push_back(Outbound::Control(OpCode::Pong, payload)),
+                }
+            }
+            Outbound::Ping(_) | Outbound::Control(..) => state.frames.push_back(message),
+        }
+        drop(state);
+
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    /// Waits for the next frame, `None` once the queue is closed and drained.
+    pub async fn pop(&self) -> Option<Outbound> {
+        loop {
+            if let Some(frame) = self.try_pop() {
+                return frame;
+            }
+
+            self.notify.notified().await;
+        }
+    }
+
+    /// `Some(None)` means closed and drained, `None` that there's nothing to write yet.
+    #[inline]
+    fn try_pop(&self) -> Option<Option<Outbound>> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.frames.pop_front() {
+            Some(frame) => {
+                if let Outbound::Text(_) = frame {
+                    state.messages -= 1;
+                }
+                Some(Some(frame))
+            }
+            None if state.closed => Some(None),
+            None => None,
+        }
+    }
+
+    /// Replaces the pending messages with the given error and close frames.
+    ///
+    /// Returns false when the queue was already closed by someone else.
+    pub fn evict(&self, error: Bytes, code: u16, reason: &str) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.closed {
+            return false;
+        }
+
+        state.frames.retain(|frame| !matches!(frame, Outbound::Text(_)));
+        self.record_dropped(state.messages as u64);
+        state.messages = 1;
+        state.frames.push_back(Outbound::Text(error));
+        state.frames.push_back(Outbound::Close(code, reason.to_string()));
+        state.closed = true;
+        drop(state);
+
+        self.notify.notify_one();
+        true
+    }
+
+    /// Stops accepting frames, the writer finishes what's already queued.
+    #[inline]
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.closed = true;
+        drop(state);
+
+        self.notify.notify_one();
+    }
+
+    #[inline]
+    fn record_dropped(&self, count: u64) {
+        self.dropped.fetch_add(count, Ordering::Relaxed);
+        self.app_state.record_dropped(count);
+    }
+
+    #[inline]
+    pub fn get_dropped_count(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for OutboundQueue {
+    fn default() -> Self {
+        Self::new(
+            SlowConsumerPolicy::default(),
+            Self::DEFAULT_CAPACITY,
+            Self::DEFAULT_MAX_OVERFLOWS,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(message: &str) -> Outbound {
+        Outbound::Text(Bytes::copy_from_slice(message.as_bytes()))
+    }
+
+    async fn drain(queue: &OutboundQueue) -> Vec<String> {
+        queue.close();
+        let mut frames = Vec::new();
+        while let Some(frame) = queue.pop().await {
+            frames.push(match frame {
+                Outbound::Text(text) | Outbound::Ping(text) => String::from_utf8_lossy(&text).into_owned(),
+                Outbound::Control(opcode, _) => format!("{:?}", opcode),
+                Outbound::Close(code, _) => format!("close {}", code),
+            });
+        }
+        frames
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_keeps_the_newest_messages() {
+        let queue = OutboundQueue::new(SlowConsumerPolicy::DropOldest, 2, 1);
+        for message in ["a", "b", "c", "d"] {
+            queue.push(text(message)).unwrap();
+        }
+
+        assert_eq!(queue.get_dropped_count(), 2);
+        assert_eq!(drain(&queue).await, ["c", "d"]);
+    }
+
+    #[tokio::test]
+    async fn drop_newest_keeps_the_oldest_messages() {
+        let queue = OutboundQueue::new(SlowConsumerPolicy::DropNewest, 2, 1);
+        for message in ["a", "b", "c", "d"] {
+            queue.push(text(message)).unwrap();
+        }
+
+        assert_eq!(queue.get_dropped_count(), 2);
+        assert_eq!(drain(&queue).await, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn disconnect_fails_after_max_overflows() {
+        let queue = OutboundQueue::new(SlowConsumerPolicy::Disconnect, 1, 2);
+        queue.push(text("a")).unwrap();
+        queue.push(text("b")).unwrap();
+        assert!(matches!(queue.push(text("c")), Err(FastSocketError::ReconnectError)));
+
+        assert_eq!(queue.get_dropped_count(), 2);
+        assert_eq!(drain(&queue).await, ["a"]);
+    }
+
+    #[tokio::test]
+    async fn control_frames_ignore_the_capacity() {
+        let queue = OutboundQueue::new(SlowConsumerPolicy::DropNewest, 1, 1);
+        queue.push(text("a")).unwrap();
+        queue.push(Outbound::Control(OpCode::Ping, Vec::new())).unwrap();
+        queue.push(Outbound::Control(OpCode::Pong, Vec::new())).unwrap();
+
+        assert_eq!(queue.get_dropped_count(), 0);
+        assert_eq!(drain(&queue).await, ["a", "Ping", "Pong"]);
+    }
+
+    #[tokio::test]
+    async fn pending_pongs_are_coalesced() {
+        let queue = OutboundQueue::new(SlowConsumerPolicy::DropNewest, 1, 1);
+        queue.push(text("a")).unwrap();
+        for i in 0..1000u32 {
+            queue.push(Outbound::Control(OpCode::Pong, i.to_be_bytes().to_vec())).unwrap();
+        }
+        queue.push(text("b")).unwrap();
+
+        assert!(matches!(queue.pop().await, Some(Outbound::Text(_))));
+        match queue.pop().await {
+            Some(Outbound::Control(OpCode::Pong, payload)) => assert_eq!(payload, 999u32.to_be_bytes()),
+            frame => panic!("expected a pong, got {:?}", frame),
+        }
+        assert_eq!(drain(&queue).await, Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn pings_ignore_the_capacity() {
+        for policy in [SlowConsumerPolicy::DropNewest, SlowConsumerPolicy::Disconnect] {
+            let queue = OutboundQueue::new(policy, 1, 1);
+            queue.push(text("a")).unwrap();
+            queue.push(Outbound::Ping(Bytes::from_static(b"ping"))).unwrap();
+
+            assert_eq!(queue.get_dropped_count(), 0);
+            assert_eq!(drain(&queue).await, ["a", "ping"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn evict_replaces_pending_messages_with_error_and_close() {
+        let queue = OutboundQueue::new(SlowConsumerPolicy::Disconnect, 3, 1);
+        queue.push(text("a")).unwrap();
+        queue.push(Outbound::Control(OpCode::Ping, Vec::new())).unwrap();
+        queue.push(text("b")).unwrap();
+
+        assert!(queue.evict(Bytes::from_static(b"error"), 4200, "reconnect"));
+        assert!(!queue.evict(Bytes::from_static(b"error"), 4200, "reconnect"));
+        assert!(matches!(queue.push(text("c")), Err(FastSocketError::ConnectionClosed)));
+
+        assert_eq!(queue.get_dropped_count(), 2);
+        assert_eq!(drain(&queue).await, ["Ping", "error", "close 4200"]);
+    }
+
+    #[tokio::test]
+    async fn evict_fails_once_closed() {
+        let queue = OutboundQueue::default();
+        queue.push(Outbound::Close(1000, String::new())).unwrap();
+
+        assert!(!queue.evict(Bytes::from_static(b"error"), 4200, "reconnect"));
+        assert_eq!(drain(&queue).await, ["close 1000"]);
+    }
+}
@@ -11,12 +11,16 @@ pub struct Payload {
     event: String,
     channel: String,
     data: Map<String, Value>,
+    raw_data: Option<String>,
+    user_id: Option<String>,
 }
 
 impl Payload {
+    pub const MAX_EVENT_NAME_LENGTH: usize = 200;
+
     #[inline]
     pub fn new(json_data: &str) -> Result<Self, FastSocketError> {
-        let payload: Value = serde_json::from_str(&json_data)
+        let payload: Value = serde_json::from_str(json_data)
             .map_err(|_| FastSocketError::InvalidMessageError)?;
 
         let obj = payload.as_object()
@@ -26,24 +30,57 @@ impl Payload {
             .and_then(Value::as_str)
             .ok_or(FastSocketError::InvalidMessageError)?
             .to_string();
+        if event.is_empty() || event.len() > Self::MAX_EVENT_NAME_LENGTH {
+            return Err(FastSocketError::InvalidEventNameError);
+        }
 
         let channel = obj.get("channel")
             .and_then(Value::as_str)
             .map(|s| s.to_string())
             .unwrap_or_else(|| "".to_string());
 
-        let data = obj.get("data")
-            .and_then(Value::as_object)
-            .map(|m| m.clone())
-            .unwrap_or_else(Map::new);
+        // Client events may carry any JSON as data, keep non-objects as-is
+        let (data, raw_data) = match obj.get("data") {
+            Some(Value::Object(data)) => (data.clone(), None),
+            Some(Value::String(data)) => (Map::new(), Some(data.clone())),
+            Some(Value::Null) | None => (Map::new(), None),
+            Some(data) => (Map::new(), Some(data.to_string())),
+        };
 
         Ok(Payload {
             event,
             channel,
             data,
+            raw_data,
+            user_id: None,
         })
     }
 
+    /// Event names in the `pusher:` and `pusher_internal:` namespaces belong to the protocol.
+    #[inline]
+    pub fn is_reserved_event(event: &str) -> bool {
+        event.starts_with("pusher:") || event.starts_with("pusher_internal:")
+    }
+
+    /// Checks an event name published by an app or a client.
+    #[inline]
+    pub fn validate_event_name(event: &str) -> Result<(), FastSocketError> {
+        if event.is_empty() || event.len() > Self::MAX_EVENT_NAME_LENGTH || Self::is_reserved_event(event) {
+            return Err(FastSocketError::InvalidEventNameError);
+        }
+
+        Ok(())
+    }
+
+    /// Size of the event data as it goes out on the wire.
+    #[inline]
+    pub fn get_data_size(&self) -> usize {
+        match &self.raw_data {
+            Some(raw_data) => raw_data.len(),
+            None => serde_json::to_string(&self.data).map(|data| data.len()).unwrap_or_default(),
+        }
+    }
+
     #[inline(always)]
     pub fn get_event(&self) -> &str {
         &self.event
@@ -59,6 +96,11 @@ impl Payload {
         &self.data
     }
 
+    #[inline(always)]
+    pub fn get_raw_data(&self) -> Option<&str> {
+        self.raw_data.as_deref()
+    }
+
     #[inline]
     pub fn get_data_str(&self, key: &str) -> Option<&str> {
         self.data.get(key)
@@ -106,7 +148,9 @@ impl Payload {
             return Err(FastSocketError::InvalidPayloadError);
         }
 
-        if !self.data.is_empty() {
+        if let Some(raw_data) = &self.raw_data {
+            map.insert(String::from("data"), Value::from(raw_data.clone()));
+        } else if !self.data.is_empty() {
             let mut data = json!(self.data.clone()).to_string();
 
             if let Some(key) = encryption_key {
@@ -141,6 +185,10 @@ impl Payload {
 
         map.insert(String::from("event"), Value::from(self.event.clone()));
 
+        if let Some(user_id) = &self.user_id {
+            map.insert(String::from("user_id"), Value::from(user_id.clone()));
+        }
+
         let data = serde_json::to_vec(&map)
             .map_err(|_| FastSocketError::InvalidPayloadError)?;
 
@@ -153,6 +201,8 @@ pub struct PayloadBuilder {
     event: Option<String>,
     channel: Option<String>,
     data: Map<String, Value>,
+    raw_data: Option<String>,
+    user_id: Option<String>,
 }
 
 impl PayloadBuilder {
@@ -174,6 +224,19 @@ impl PayloadBuilder {
         self
     }
 
+    /// Sets data that is sent as-is, e.g. a pre-serialized string from the HTTP API.
+    #[inline]
+    pub fn raw_data<S: Into<String>>(mut self, data: S) -> Self {
+        self.raw_data = Some(data.into());
+        self
+    }
+
+    #[inline]
+    pub fn user_id<S: Into<String>>(mut self, user_id: S) -> Self {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
     #[inline]
     pub fn add_data<S: Into<String>, V: Into<Value>>(mut self, key: S, value: V) -> Self {
         self.data.insert(key.into(), value.into());
@@ -186,12 +249,10 @@ impl PayloadBuilder {
 
         Ok(Payload {
             event,
-            channel: if self.channel.is_none() {
-                "".to_string()
-            } else {
-                self.channel.unwrap()
-            },
+            channel: self.channel.unwrap_or_default(),
             data: self.data,
+            raw_data: self.raw_data,
+            user_id: self.user_id,
         })
     }
 }
@@ -1,7 +1,7 @@
 use crate::channel::Channel;
 use crate::client::Client;
 use async_trait::async_trait;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use serde_json::{Map, Value};
 use tokio::sync::RwLock;
@@ -9,10 +9,73 @@ use crate::errors::FastSocketError;
 use crate::logger::Log;
 use crate::payload::Payload;
 
+struct Member {
+    user_info: Value,
+    sockets: HashSet<String>,
+}
+
+/// Presence members keyed by user id, a user stays a member while any of their sockets is subscribed.
+#[derive(Default)]
+struct Members {
+    by_user: HashMap<String, Member>,
+    by_socket: HashMap<String, String>,
+}
+
+impl Members {
+    /// Adds a socket to the user's membership, returns true for the user's first socket.
+    ///
+    /// New users are turned away once the channel has `max_members` of them.
+    #[inline]
+    fn join(
+        &mut self,
+        socket_id: &str,
+        user_id: &str,
+        user_info: Value,
+        max_members: usize,
+    ) -> Result<bool, FastSocketError> {
+        if !self.by_user.contains_key(user_id) && self.by_user.len() >= max_members {
+            return Err(FastSocketError::PresenceMembersLimitError);
+        }
+
+        if let Some(previous) = self.by_socket.insert(socket_id.to_string(), user_id.to_string()) {
+            self.leave_user(socket_id, &previous);
+        }
+
+        let member = self.by_user.entry(user_id.to_string()).or_insert_with(|| Member {
+            user_info: Value::Null,
+            sockets: HashSet::new(),
+        });
+        member.user_info = user_info;
+        member.sockets.insert(socket_id.to_string());
+        Ok(member.sockets.len() == 1)
+    }
+
+    /// Removes a socket, returns the user id when it was the user's last socket.
+    #[inline]
+    fn leave(&mut self, socket_id: &str) -> Option<String> {
+        let user_id = self.by_socket.remove(socket_id)?;
+        self.leave_user(socket_id, &user_id).then_some(user_id)
+    }
+
+    #[inline]
+    fn leave_user(&mut self, socket_id: &str, user_id: &str) -> bool {
+        let Some(member) = self.by_user.get_mut(user_id) else {
+            return false;
+        };
+        member.sockets.remove(socket_id);
+        if !member.sockets.is_empty() {
+            return false;
+        }
+
+        self.by_user.remove(user_id);
+        true
+    }
+}
+
 pub struct PresenceChannel {
     name: String,
     connections: RwLock<HashMap<String, Arc<Client>>>,
-    channel_data: RwLock<HashMap<String, Value>>,
+    members: RwLock<Members>,
 }
 
 impl PresenceChannel {
@@ -21,37 +84,77 @@ impl PresenceChannel {
         Self {
             name,
             connections: RwLock::new(HashMap::with_capacity(32)),
-            channel_data: RwLock::new(HashMap::with_capacity(32)),
+            members: RwLock::new(Members::default()),
         }
     }
 
     #[inline]
     async fn channel_data(&self) -> Map<String, Value> {
-        let mut map = Map::new();
+        let read_guard = self.members.read().await;
+        let ids: Vec<Value> = read_guard.by_user.keys()
+            .map(|user_id| Value::from(user_id.as_str()))
+            .collect();
+        let hash: Map<String, Value> = read_guard.by_user.iter()
+            .map(|(user_id, member)| (user_id.clone(), member.user_info.clone()))
+            .collect();
+        let count = read_guard.by_user.len();
+        drop(read_guard);
+
         let mut presence = Map::new();
-        presence.insert(String::from("ids"), self.get_client_ids().await); // all user_id from channel_data
-        presence.insert(String::from("hash"), self.get_hash().await); // all channel_data
-        presence.insert(String::from("count"), Value::from(self.get_clients_count().await));
+        presence.insert(String::from("ids"), Value::Array(ids));
+        presence.insert(String::from("hash"), Value::Object(hash));
+        presence.insert(String::from("count"), Value::from(count));
+
+        let mut map = Map::new();
         map.insert(String::from("presence"), Value::Object(presence));
         map
     }
 
-    #[inline]
-    async fn get_client_ids(&self) -> Value {
-        let mut ids = Vec::new();
-        for (_, val) in self.channel_data.read().await.iter() {
-            ids.push(val.get("user_id").unwrap().clone());
+    /// Extracts the member's user id and optional user info from `channel_data`.
+    ///
+    /// Pusher user ids are strings, but numbers are accepted too.
+    fn parse_channel_data(payload: &Payload, max_size: usize) -> Result<(String, Value), FastSocketError> {
+        let channel_data = payload.get_data_str("channel_data")
+            .ok_or(FastSocketError::InvalidChannelDataError)?;
+        if channel_data.len() > max_size {
+            return Err(FastSocketError::MemberDataTooLargeError);
         }
-        Value::Array(ids)
+
+        let channel_data: Map<String, Value> = serde_json::from_str(channel_data)
+            .map_err(|_| FastSocketError::InvalidChannelDataError)?;
+
+        let user_id = match channel_data.get("user_id") {
+            Some(Value::String(user_id)) if !user_id.is_empty() => user_id.clone(),
+            Some(Value::Number(user_id)) => user_id.to_string(),
+            _ => return Err(FastSocketError::InvalidChannelDataError),
+        };
+
+        let user_info = match channel_data.get("user_info") {
+            None | Some(Value::Null) => Value::Null,
+            Some(Value::Object(user_info)) => Value::Object(user_info.clone()),
+            Some(_) => return Err(FastSocketError::InvalidChannelDataError),
+        };
+
+        Ok((user_id, user_info))
     }
 
-    #[inline]
-    async fn get_hash(&self) -> Value {
-        let mut hash = Map::new();
-        for (_, val) in self.channel_data.read().await.iter() {
-            hash.insert(String::from(val.get("user_id").unwrap().to_string()), val.get("user_info").unwrap().clone());
-        }
-        Value::Object(hash)
+    /// Adds the member's socket to the channel and confirms the subscription.
+    async fn accept_member(&self, client: Arc<Client>, user_id: &str) -> Result<(), FastSocketError> {
+        Log::debug("Subscribing");
+        self.save_connection(client.clone()).await?;
+
+        let user_manager = client.get_user_manager();
+        let mut write_guard = user_manager.write().await;
+        write_guard.add_socket(client.get_app().get_id(), user_id, client.clone());
+        drop(write_guard);
+
+        let response = Payload::builder()
+            .event("pusher_internal:subscription_succeeded")
+            .channel(self.get_name())
+            .data(self.channel_data().await)
+            .build()?;
+
+        client.socket().send(&response).await
     }
 }
 
@@ -68,46 +171,86 @@ impl Channel for PresenceChannel {
     }
 
     #[inline]
-    async fn subscribe(&mut self, client: Arc<Client>, payload: &Payload) -> Result<(), FastSocketError> {
+    async fn subscribe(&self, client: Arc<Client>, payload: &Payload) -> Result<(), FastSocketError> {
         let result = self.verify_signature(client.clone(), payload).await;
         if result.is_err() {
             return Err(FastSocketError::InvalidSignatureError)
         }
 
-        Log::debug("Subscribing");
-        self.save_connection(client.clone()).await?;
-
-        let channel_data: Value = serde_json::from_str(payload.get_data_str("channel_data").unwrap()).unwrap();
+        let app = client.get_app();
+        let (user_id, user_info) = Self::parse_channel_data(payload, app.get_max_presence_member_size())?;
 
-        self.channel_data.get_mut().insert(client.get_socket_id().to_string(), channel_data.clone());
+        let mut write_guard = self.members.write().await;
+        let rejoined = write_guard.by_socket.contains_key(client.get_socket_id());
+        let first_socket = write_guard.join(
+            client.get_socket_id(),
+            &user_id,
+            user_info.clone(),
+            app.get_max_presence_members_per_channel(),
+        )?;
+        drop(write_guard);
 
-        let response = Payload::builder()
-            .event("pusher_internal:subscription_succeeded")
-            .channel(self.get_name())
-            .data(self.channel_data().await)
-            .build();
-        if response.is_err() {
-            return Err(response.err().unwrap());
+        if let Err(e) = self.accept_member(client.clone(), &user_id).await {
+            // Gives back the member slot, member_added was never sent so neither is member_removed
+            if !rejoined {
+                self.members.write().await.leave(client.get_socket_id());
+            }
+            return Err(e);
         }
 
-        let socket = client.socket();
-        let mut guard = socket.lock().await;
-        guard.send(&response.unwrap()).await?;
-        drop(guard);
+        if !first_socket {
+            return Ok(());
+        }
 
-        let kv_channel_data = channel_data.as_object().unwrap();
+        let mut data = Map::new();
+        data.insert(String::from("user_id"), Value::from(user_id));
+        data.insert(String::from("user_info"), user_info);
         let event = Payload::builder()
             .event("pusher_internal:member_added")
             .channel(self.get_name())
-            .data(kv_channel_data.clone())
-            .build();
+            .data(data)
+            .build()?;
+
+        self.broadcast_to_others(client, &event).await
+    }
+
+    #[inline]
+    async fn get_user_count(&self) -> Option<u64> {
+        Some(self.members.read().await.by_user.len() as u64)
+    }
 
-        self.broadcast_to_others(client, &event?).await
+    #[inline]
+    async fn get_user_ids(&self) -> Vec<String> {
+        let read_guard = self.members.read().await;
+        read_guard.by_user.keys().cloned().collect()
     }
 
     #[inline]
-    async fn unsubscribe(&mut self, socket_id: &str) -> Result<(), FastSocketError> {
-        self.channel_data.get_mut().remove(socket_id);
-        self.default_unsubscribe(socket_id).await
+    async fn get_user_id(&self, socket_id: &str) -> Option<String> {
+        let read_guard = self.members.read().await;
+        read_guard.by_socket.get(socket_id).cloned()
+    }
+
+    #[inline]
+    async fn unsubscribe(&self, socket_id: &str) -> Result<(), FastSocketError> {
+        let mut write_guard = self.members.write().await;
+        let departed = write_guard.leave(socket_id);
+        drop(write_guard);
+
+        self.default_unsubscribe(socket_id).await?;
+
+        if let Some(user_id) = departed {
+            let mut data = Map::new();
+            data.insert(String::from("user_id"), Value::from(user_id));
+            let event = Payload::builder()
+                .event("pusher_internal:member_removed")
+                .channel(self.get_name())
+                .data(data)
+                .build()?;
+
+            self.broadcast(&event).await?;
+        }
+
+        Ok(())
     }
 }
@@ -35,7 +35,7 @@ impl Channel for PrivateChannel {
     }
 
     #[inline]
-    async fn subscribe(&mut self, client: Arc<Client>, payload: &Payload) -> Result<(), FastSocketError> {
+    async fn subscribe(&self, client: Arc<Client>, payload: &Payload) -> Result<(), FastSocketError> {
         let result = self.verify_signature(client.clone(), payload).await;
         if result.is_err() {
             return Err(FastSocketError::InvalidSignatureError)
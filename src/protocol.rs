@@ -0,0 +1,46 @@
+use crate::errors::FastSocketError;
+use crate::request_auth::parse_query;
+use std::ops::RangeInclusive;
+
+/// Protocol versions the server speaks, older pusher-js releases send 5 or 6.
+pub const SUPPORTED_PROTOCOLS: RangeInclusive<u8> = 5..=7;
+
+/// What the client announced in its connection query string,
+/// e.g. `?protocol=7&client=js&version=8.4.0`.
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    protocol: u8,
+    client: Option<String>,
+    version: Option<String>,
+}
+
+impl ConnectionInfo {
+    pub fn from_query(query: Option<&str>) -> Result<Self, FastSocketError> {
+        let mut params = parse_query(query);
+        let protocol = params.get("protocol")
+            .and_then(|protocol| protocol.parse::<u8>().ok())
+            .filter(|protocol| SUPPORTED_PROTOCOLS.contains(protocol))
+            .ok_or(FastSocketError::UnsupportedProtocolError)?;
+
+        Ok(Self {
+            protocol,
+            client: params.remove("client").filter(|client| !client.is_empty()),
+            version: params.remove("version").filter(|version| !version.is_empty()),
+        })
+    }
+
+    #[inline]
+    pub fn get_protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    #[inline]
+    pub fn get_client(&self) -> Option<&str> {
+        self.client.as_deref()
+    }
+
+    #[inline]
+    pub fn get_version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+}
@@ -0,0 +1,297 @@
+use crate::app::App;
+use crate::app_manager::AppManager;
+use crate::errors::FastSocketError;
+use hmac::{Hmac, Mac};
+use hyper::Method;
+use md5::{Digest, Md5};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Verifies the Pusher HTTP API request signature.
+///
+/// Signed requests carry `auth_key`, `auth_timestamp`, `auth_version` and
+/// `auth_signature` in the query string, plus `body_md5` when a body is sent.
+/// The signature is an HMAC-SHA256 over `METHOD\npath\nsorted-query` keyed
+/// with the app secret.
+pub struct RequestAuth {
+    app_manager: Arc<Box<dyn AppManager>>,
+}
+
+impl RequestAuth {
+    const MAX_TIMESTAMP_SKEW: u64 = 600;
+
+    #[inline]
+    pub fn new(app_manager: Arc<Box<dyn AppManager>>) -> Self {
+        Self { app_manager }
+    }
+
+    pub fn verify(
+        &self,
+        method: &Method,
+        path: &str,
+        query: Option<&str>,
+        body: &[u8],
+    ) -> Result<Arc<App>, FastSocketError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        self.verify_at(method, path, query, body, now)
+    }
+
+    fn verify_at(
+        &self,
+        method: &Method,
+        path: &str,
+        query: Option<&str>,
+        body: &[u8],
+        now: u64,
+    ) -> Result<Arc<App>, FastSocketError> {
+        let params = parse_query(query);
+
+        let key = params.get("auth_key")
+            .ok_or(FastSocketError::MissingAuthParamsError)?;
+        let timestamp = params.get("auth_timestamp")
+            .ok_or(FastSocketError::MissingAuthParamsError)?;
+        let signature = params.get("auth_signature")
+            .ok_or(FastSocketError::MissingAuthParamsError)?;
+
+        let app = self.app_manager.find_by_key(key)
+            .ok_or(FastSocketError::InvalidAppKeyError)?;
+
+        Self::verify_timestamp(timestamp, now)?;
+        Self::verify_body(&params, body)?;
+
+        verify_hmac(app.get_secret(), &Self::string_to_sign(method, path, &params), signature)?;
+
+        Ok(app)
+    }
+
+    #[inline]
+    fn verify_timestamp(timestamp: &str, now: u64) -> Result<(), FastSocketError> {
+        let timestamp: u64 = timestamp.parse()
+            .map_err(|_| FastSocketError::AuthTimestampExpiredError)?;
+
+        if now.abs_diff(timestamp) > Self::MAX_TIMESTAMP_SKEW {
+            return Err(FastSocketError::AuthTimestampExpiredError);
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn verify_body(params: &BTreeMap<String, String>, body: &[u8]) -> Result<(), FastSocketError> {
+        let body_md5 = params.get("body_md5");
+        if body.is_empty() && body_md5.is_none() {
+            return Ok(());
+        }
+
+        let body_md5 = body_md5.ok_or(FastSocketError::InvalidBodyMd5Error)?;
+        if hex::encode(Md5::digest(body)) != body_md5.to_lowercase() {
+            return Err(FastSocketError::InvalidBodyMd5Error);
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn string_to_sign(method: &Method, path: &str, params: &BTreeMap<String, String>) -> String {
+        let query = params.iter()
+            .filter(|(key, _)| key.as_str() != "auth_signature")
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<String>>()
+            .join("&");
+
+        format!("{}\n{}\n{}", method.as_str(), path, query)
+    }
+}
+
+/// Checks a hex encoded HMAC-SHA256 signature of `data` in constant time.
+pub fn verify_hmac(secret: &str, data: &str, signature: &str) -> Result<(), FastSocketError> {
+    let expected = hex::decode(signature)
+        .map_err(|_| FastSocketError::InvalidSignatureError)?;
+
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .map_err(|_| FastSocketError::InvalidSignatureError)?;
+    mac.update(data.as_bytes());
+    mac.verify_slice(&expected)
+        .map_err(|_| FastSocketError::InvalidSignatureError)
+}
+
+/// Parses a query string into URL-decoded parameters with lowercased keys, sorted by key.
+pub fn parse_query(query: Option<&str>) -> BTreeMap<String, String> {
+    query.unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (url_decode(key).to_lowercase(), url_decode(value))
+        })
+        .collect()
+}
+
+#[inline]
+fn url_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let byte = std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match byte {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_app_manager::JsonAppManager;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Worked example from the Pusher HTTP API docs, also used by pusher-http-go's tests.
+    const KEY: &str = "278d425bdf160c739803";
+    const SECRET: &str = "7ad3773142a6692b25b8";
+    const TIMESTAMP: u64 = 1353088179;
+    const PATH: &str = "/apps/3/events";
+    const BODY: &str = r#"{"name":"foo","channels":["project-3"],"data":"{\"some\":\"data\"}"}"#;
+    const BODY_MD5: &str = "ec365a775a4cd0599faeb73354201b6f";
+    const SIGNATURE: &str = "da454824c97ba181a32ccc17a72625ba02771f50b50e1e7430e47a1f3f457e6c";
+
+    fn request_auth() -> RequestAuth {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "fastsocket-request-auth-{}-{}.json",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed),
+        ));
+        let apps = format!(
+            r#"[{{"id":"3","key":"{}","secret":"{}","name":"Test","host":"localhost","path":"/app/","capacity":100,"flags":0}}]"#,
+            KEY, SECRET,
+        );
+        std::fs::write(&path, apps).unwrap();
+        let app_manager = JsonAppManager::new(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        RequestAuth::new(app_manager)
+    }
+
+    fn query(body_md5: Option<&str>, signature: &str) -> String {
+        let mut query = format!("auth_key={}&auth_timestamp={}&auth_version=1.0", KEY, TIMESTAMP);
+        if let Some(body_md5) = body_md5 {
+            query.push_str(&format!("&body_md5={}", body_md5));
+        }
+        format!("{}&auth_signature={}", query, signature)
+    }
+
+    #[test]
+    fn string_to_sign_matches_pusher_example() {
+        let params = parse_query(Some(&query(Some(BODY_MD5), SIGNATURE)));
+        assert_eq!(
+            RequestAuth::string_to_sign(&Method::POST, PATH, &params),
+            format!(
+                "POST\n{}\nauth_key={}&auth_timestamp={}&auth_version=1.0&body_md5={}",
+                PATH, KEY, TIMESTAMP, BODY_MD5,
+            ),
+        );
+    }
+
+    #[test]
+    fn verify_accepts_pusher_signature() {
+        let query = query(Some(BODY_MD5), SIGNATURE);
+        let app = request_auth()
+            .verify_at(&Method::POST, PATH, Some(&query), BODY.as_bytes(), TIMESTAMP)
+            .unwrap();
+        assert_eq!(app.get_key(), KEY);
+    }
+
+    #[test]
+    fn verify_rejects_tampered_request() {
+        let auth = request_auth();
+        let query = query(Some(BODY_MD5), SIGNATURE);
+        let result = auth.verify_at(&Method::POST, "/apps/4/events", Some(&query), BODY.as_bytes(), TIMESTAMP);
+        assert!(matches!(result, Err(FastSocketError::InvalidSignatureError)));
+
+        let query = query.replace("auth_signature=da", "auth_signature=db");
+        let result = auth.verify_at(&Method::POST, PATH, Some(&query), BODY.as_bytes(), TIMESTAMP);
+        assert!(matches!(result, Err(FastSocketError::InvalidSignatureError)));
+    }
+
+    #[test]
+    fn verify_rejects_body_md5_mismatch() {
+        let query = query(Some(BODY_MD5), SIGNATURE);
+        let result = request_auth().verify_at(&Method::POST, PATH, Some(&query), b"{}", TIMESTAMP);
+        assert!(matches!(result, Err(FastSocketError::InvalidBodyMd5Error)));
+    }
+
+    #[test]
+    fn verify_rejects_body_without_body_md5() {
+        let query = query(None, SIGNATURE);
+        let result = request_auth().verify_at(&Method::POST, PATH, Some(&query), BODY.as_bytes(), TIMESTAMP);
+        assert!(matches!(result, Err(FastSocketError::InvalidBodyMd5Error)));
+    }
+
+    #[test]
+    fn verify_body_allows_empty_body_without_body_md5() {
+        assert!(RequestAuth::verify_body(&BTreeMap::new(), b"").is_ok());
+    }
+
+    #[test]
+    fn verify_timestamp_allows_600_seconds_of_skew() {
+        let timestamp = TIMESTAMP.to_string();
+        assert!(RequestAuth::verify_timestamp(&timestamp, TIMESTAMP + 600).is_ok());
+        assert!(RequestAuth::verify_timestamp(&timestamp, TIMESTAMP - 600).is_ok());
+        assert!(matches!(
+            RequestAuth::verify_timestamp(&timestamp, TIMESTAMP + 601),
+            Err(FastSocketError::AuthTimestampExpiredError)
+        ));
+        assert!(matches!(
+            RequestAuth::verify_timestamp(&timestamp, TIMESTAMP - 601),
+            Err(FastSocketError::AuthTimestampExpiredError)
+        ));
+        assert!(matches!(
+            RequestAuth::verify_timestamp("soon", TIMESTAMP),
+            Err(FastSocketError::AuthTimestampExpiredError)
+        ));
+    }
+
+    #[test]
+    fn url_decode_handles_plus_and_escapes() {
+        assert_eq!(url_decode("a+b"), "a b");
+        assert_eq!(url_decode("a%2Bb%2fc"), "a+b/c");
+        assert_eq!(url_decode("%E2%82%AC"), "\u{20ac}");
+    }
+
+    #[test]
+    fn url_decode_keeps_invalid_escapes() {
+        assert_eq!(url_decode("100%zz"), "100%zz");
+        assert_eq!(url_decode("100%"), "100%");
+        assert_eq!(url_decode("100%4"), "100%4");
+    }
+
+    #[test]
+    fn parse_query_lowercases_and_sorts_keys() {
+        let params = parse_query(Some("B=2&a=1&Auth_Key=k+1"));
+        let params: Vec<_> = params.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        assert_eq!(params, vec![("a", "1"), ("auth_key", "k 1"), ("b", "2")]);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::app::App;
+use crate::app_manager::AppManager;
+use crate::channel_manager::ChannelManager;
+use crate::errors::FastSocketError;
+use crate::health::Health;
+use crate::http_api::HttpApi;
+use crate::logger::Log;
+use crate::protocol::ConnectionInfo;
+use crate::websocket::WebSocket;
+use fastwebsockets::upgrade;
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{HeaderValue, CONTENT_TYPE, UPGRADE};
+use hyper::{Method, Request, Response, StatusCode};
+use serde_json::{json, Map, Value};
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// Which routes a listener answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Routes {
+    /// WebSocket, REST API and admin routes on a single listener.
+    All,
+    /// WebSocket and REST API only, admin routes live on their own listener.
+    Public,
+    /// Health, readiness and metrics only.
+    Admin,
+}
+
+#[allow(clippy::redundant_allocation)]
+pub struct Router {
+    routes: Routes,
+    websocket: Arc<Box<WebSocket>>,
+    app_manager: Arc<Box<dyn AppManager>>,
+    channel_manager: Arc<Box<dyn ChannelManager>>,
+    http_api: Arc<Box<HttpApi>>,
+    health: Arc<Health>,
+}
+
+impl Router {
+    pub fn new(
+        routes: Routes,
+        websocket: Arc<Box<WebSocket>>,
+        app_manager: Arc<Box<dyn AppManager>>,
+        channel_manager: Arc<Box<dyn ChannelManager>>,
+        http_api: Arc<Box<HttpApi>>,
+        health: Arc<Health>,
+    ) -> Arc<Box<Self>> {
+        Arc::new(Box::new(Self {
+            routes,
+            websocket,
+            app_manager,
+            channel_manager,
+            http_api,
+            health,
+        }))
+    }
+
+    /// Answers every request, failures included, so hyper never drops a connection without a response.
+    pub async fn handle(&self, req: Request<Incoming>) -> Response<Full<Bytes>> {
+        let public = self.routes != Routes::Admin;
+        let admin = self.routes != Routes::Public;
+        let path = req.uri().path();
+
+        if public && path.starts_with("/apps/") {
+            return self.http_api.handle(req).await;
+        }
+
+        if public && upgrade::is_upgrade_request(&req) {
+            if self.health.is_draining() {
+                return HttpApi::error(StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down");
+            }
+            return self.upgrade(req).await;
+        }
+
+        match (req.method(), path) {
+            (&Method::GET, "/health") if admin => HttpApi::json(StatusCode::OK, json!({ "status": "ok" })),
+            (&Method::GET, "/ready") if admin => self.ready(),
+            (&Method::GET, "/metrics") if admin => self.metrics().await,
+            (&Method::GET, "/connections") if admin => self.connections(),
+            _ if public && self.resolve_app(path).is_ok() => {
+                let mut response = HttpApi::error(StatusCode::UPGRADE_REQUIRED, "WebSocket upgrade required");
+                response.headers_mut().insert(UPGRADE, HeaderValue::from_static("websocket"));
+                response
+            }
+            _ => HttpApi::error(StatusCode::NOT_FOUND, "Not found"),
+        }
+    }
+
+    /// Finds the app a client connects to, pusher-js uses `{app path}{app key}` as the URL path.
+    fn resolve_app(&self, path: &str) -> Result<Arc<App>, FastSocketError> {
+        let (prefix, key) = match path.rsplit_once('/') {
+            Some((prefix, key)) if !key.is_empty() => (prefix, key),
+            _ => return Err(FastSocketError::InvalidAppPathError),
+        };
+
+        let app = self.app_manager.find_by_key(key).ok_or(FastSocketError::InvalidAppKeyError)?;
+        if app.get_path().trim_end_matches('/') != prefix {
+            return Err(FastSocketError::InvalidAppPathError);
+        }
+
+        Ok(app)
+    }
+
+    async fn upgrade(&self, mut req: Request<Incoming>) -> Response<Full<Bytes>> {
+        let (response, fut) = match upgrade::upgrade(&mut req) {
+            Ok(upgrade) => upgrade,
+            Err(e) => {
+                Log::error(&format!("Upgrade failed: {:?}", e));
+                return HttpApi::error(StatusCode::BAD_REQUEST, &FastSocketError::UpgradeFailedError.to_string());
+            }
+        };
+
+        let connection = self.resolve_app(req.uri().path())
+            .and_then(|app| Ok((app, ConnectionInfo::from_query(req.uri().query())?)));
+        let ws = self.websocket.clone();
+
+        tokio::task::spawn(async move {
+            let (app, connection_info) = match connection {
+                Ok(connection) => connection,
+                Err(e) => {
+                    Log::error(&format!("Refusing connection to {}: {:?}", req.uri(), e));
+                    if let Err(e) = ws.reject(fut, e).await {
+                        Log::error(&format!("Error rejecting client: {:?}", e));
+                    }
+                    return;
+                }
+            };
+            let handle_future = ws.handle_client(fut, app, connection_info);
+            let pinned_future = Box::pin(handle_future);
+            if let Err(e) = tokio::task::unconstrained(pinned_future).await {
+                eprintln!("Error handling client: {:?}", e);
+            }
+        });
+
+        response.map(|_| Full::default())
+    }
+
+    /// Ready once every component is up, a draining server is never ready.
+    fn ready(&self) -> Response<Full<Bytes>> {
+        let up = |up: bool| if up { "up" } else { "down" };
+        let components = [
+            ("app_manager", up(self.app_manager.is_ready())),
+            ("listener", up(self.health.is_accepting())),
+            ("shutdown", if self.health.is_draining() { "draining" } else { "up" }),
+        ];
+        let ready = components.iter().all(|(_, status)| *status == "up");
+
+        let components: Map<String, Value> = components.iter()
+            .map(|(name, status)| (name.to_string(), json!({ "status": status })))
+            .collect();
+        let body = json!({
+            "status": if ready { "ready" } else { "not_ready" },
+            "components": components,
+        });
+
+        let code = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+        HttpApi::json(code, body)
+    }
+
+    /// Live connections with their client details and dropped message counts.
+    fn connections(&self) -> Response<Full<Bytes>> {
+        let mut clients = self.websocket.get_clients();
+        clients.sort_by(|a, b| a.get_socket_id().cmp(b.get_socket_id()));
+
+        let connections: Vec<Value> = clients.iter()
+            .map(|client| {
+                let info = client.get_connection_info();
+                json!({
+                    "socket_id": client.get_socket_id(),
+                    "app_id": client.get_app().get_id(),
+                    "protocol": info.get_protocol(),
+                    "client": info.get_client(),
+                    "version": info.get_version(),
+                    "dropped_messages": client.get_socket().get_dropped_count(),
+                })
+            })
+            .collect();
+
+        HttpApi::json(StatusCode::OK, json!({ "connections": connections }))
+    }
+
+    /// Per-app gauges in the Prometheus text format.
+    async fn metrics(&self) -> Response<Full<Bytes>> {
+        let mut connections = String::from("# TYPE fastsocket_connections gauge\n");
+        let mut channels = String::from("# TYPE fastsocket_channels gauge\n");
+        let mut dropped = String::from("# TYPE fastsocket_dropped_messages_total counter\n");
+
+        let mut apps = self.app_manager.get_apps();
+        apps.sort_by(|a, b| a.get_id().cmp(b.get_id()));
+        for app in apps {
+            let _ = writeln!(connections, "fastsocket_connections{{app_id=\"{}\"}} {}", app.get_id(), app.get_connection_count());
+
+            let mut occupied = 0;
+            for channel in self.channel_manager.get_channels(app.get_id()) {
+                if channel.has_connection().await {
+                    occupied += 1;
+                }
+            }
+            let _ = writeln!(channels, "fastsocket_channels{{app_id=\"{}\"}} {}", app.get_id(), occupied);
+            let _ = writeln!(dropped, "fastsocket_dropped_messages_total{{app_id=\"{}\"}} {}", app.get_id(), app.get_dropped_message_count());
+        }
+        connections.push_str(&channels);
+        connections.push_str(&dropped);
+
+        let mut response = Response::new(Full::new(Bytes::from(connections)));
+        response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("text/plain; version=0.0.4"));
+        response
+    }
+}
@@ -0,0 +1,8 @@
+use crate::client::Client;
+use std::sync::Arc;
+
+pub trait UserManager: Send + Sync {
+    fn add_socket(&mut self, app_id: &str, user_id: &str, client: Arc<Client>);
+    fn get_sockets(&self, app_id: &str, user_id: &str) -> Vec<Arc<Client>>;
+    fn remove_socket(&mut self, app_id: &str, socket_id: &str);
+}
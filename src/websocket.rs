@@ -5,37 +5,92 @@ use crate::client::Client;
 use crate::errors::FastSocketError;
 use crate::logger::Log;
 use crate::payload::{Payload, PayloadBuilder};
+use crate::protocol::ConnectionInfo;
+use crate::user_manager::UserManager;
 use crate::websocket_connection::WebsocketConnection;
+use dashmap::DashMap;
 use fastwebsockets::{upgrade, OpCode, WebSocketError};
 use std::io::Read;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{watch, RwLock};
 use crate::message_factory::MessageFactory;
+use crate::outbound_queue::OutboundQueue;
+
+/// How long a closing connection may take to flush its last frames.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+enum ReadEvent<F> {
+    Frame(F),
+    Terminated,
+    Shutdown,
+    Closed,
+    Idle,
+}
 
 #[derive(Clone)]
 pub struct WebSocket {
     app_manager: Arc<Box<dyn AppManager>>,
-    channel_manager: Arc<RwLock<Box<dyn ChannelManager>>>,
+    channel_manager: Arc<Box<dyn ChannelManager>>,
+    user_manager: Arc<RwLock<Box<dyn UserManager>>>,
+    shutdown: watch::Sender<bool>,
+    clients: Arc<DashMap<String, Arc<Client>>>,
 }
 
 impl WebSocket {
     pub fn new(
         app_manager: Arc<Box<dyn AppManager>>,
-        channel_manager: Arc<RwLock<Box<dyn ChannelManager>>>,
+        channel_manager: Arc<Box<dyn ChannelManager>>,
+        user_manager: Arc<RwLock<Box<dyn UserManager>>>,
     ) -> Arc<Box<Self>> {
         Arc::new(Box::new(Self {
             app_manager,
             channel_manager,
+            user_manager,
+            shutdown: watch::channel(false).0,
+            clients: Arc::new(DashMap::new()),
         }))
     }
 
+    /// Disconnects every client with a reconnect error, so they move to another server.
+    #[inline]
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    /// Live connections over all apps.
+    #[inline]
+    pub fn get_connection_count(&self) -> u64 {
+        self.app_manager.get_apps()
+            .iter()
+            .map(|app| app.get_connection_count())
+            .sum()
+    }
+
+    /// Live clients over all apps.
+    #[inline]
+    pub fn get_clients(&self) -> Vec<Arc<Client>> {
+        self.clients.iter()
+            .map(|client| client.value().clone())
+            .collect()
+    }
+
     pub async fn on_open(&self, client: Arc<Client>) {
-        Log::debug("Connection opened");
+        let info = client.get_connection_info();
+        Log::debug(&format!(
+            "Connection opened: {} (protocol {}, client {}, version {})",
+            client.get_socket_id(),
+            info.get_protocol(),
+            info.get_client().unwrap_or("unknown"),
+            info.get_version().unwrap_or("unknown"),
+        ));
+
+        self.clients.insert(client.get_socket_id().to_string(), client.clone());
 
         let builder = PayloadBuilder::default()
             .event("pusher:connection_established")
             .add_data("socket_id", client.get_socket_id())
-            .add_data("activity_timeout", 30);
+            .add_data("activity_timeout", client.get_app().get_activity_timeout());
 
         let payload = builder.build();
         if payload.is_err() {
@@ -43,34 +98,78 @@ impl WebSocket {
             eprintln!("Failed to build payload {:?}", payload);
             return;
         }
-        let socket = client.get_socket();
-        let mut guard = socket
-            .lock()
-            .await;
-
-        let result = guard
+        let result = client.get_socket()
             .send(&payload.unwrap())
             .await;
 
-        drop(guard);
-
         if result.is_err() {
             Log::error(&format!("Failed to send payload: {:?}", result));
-            return;
         }
     }
 
-    pub async fn on_close(&self, _client: Arc<Client>) {
+    pub async fn on_close(&self, client: Arc<Client>) {
         Log::debug("Connection closed");
+        self.clients.remove(client.get_socket_id());
+
+        let dropped = client.get_socket().get_dropped_count();
+        if dropped > 0 {
+            Log::info(&format!(
+                "Dropped {} messages for {} of app {}",
+                dropped,
+                client.get_socket_id(),
+                client.get_app().get_id(),
+            ));
+        }
+
+        self.channel_manager.remove_from_all_channels(client.clone()).await;
+
+        let mut write_guard = self.user_manager.write().await;
+        write_guard.remove_socket(client.get_app().get_id(), client.get_socket_id());
+        drop(write_guard);
     }
 
-    pub async fn on_error(&self, _client: Arc<Client>) {
-        Log::debug("Error occurred");
+    /// Tells the client about the error, returns true when the connection has to be closed.
+    pub async fn on_error(&self, client: Arc<Client>, error: &FastSocketError) -> bool {
+        Log::debug(&format!("Error occurred: {:?}", error));
+        if !error.is_client_error() {
+            return false;
+        }
+
+        let socket = client.get_socket();
+        let result = socket.send_error(error).await;
+        if result.is_err() {
+            Log::error(&format!("Failed to send error: {:?}", result));
+        }
+
+        match error.close_code() {
+            Some(code) => {
+                let _ = socket.close(code, &error.to_string()).await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Completes the upgrade only to tell the client why it can't connect.
+    pub async fn reject(
+        &self,
+        fut: upgrade::UpgradeFut,
+        error: FastSocketError,
+    ) -> Result<(), WebSocketError> {
+        Log::error(&format!("Rejecting connection: {:?}", error));
+        let (connection, _reader) = WebsocketConnection::new(fut.await?, OutboundQueue::default());
+
+        let _ = connection.send_error(&error).await;
+        if let Some(code) = error.close_code() {
+            let _ = connection.close(code, &error.to_string()).await;
+        }
+        connection.shutdown();
+
+        Ok(())
     }
 
     pub async fn get_payload(
         &self,
-        client: Arc<Client>,
         frame: fastwebsockets::Frame<'_>,
     ) -> Result<Option<Payload>, FastSocketError> {
         match frame.opcode {
@@ -80,34 +179,18 @@ impl WebSocket {
                 let c: Result<Vec<u8>, _> = frame.payload.bytes().collect();
                 if let Err(e) = c {
                     Log::error(&format!("Error reading payload: {:?}", e));
-                    self.on_error(client).await;
                     return Err(FastSocketError::ErrorReadingPayload);
                 }
                 let content = String::from_utf8(c.unwrap());
 
                 if content.is_err() {
                     Log::error(&format!("Error transforming payload: {:?}", content));
-                    self.on_error(client).await;
                     return Err(FastSocketError::ErrorDecodingPayload);
                 }
                 let content = content.unwrap();
                 Log::debug(&format!("Received message: {:?}", content));
                 Ok(Option::from(Payload::new(content.as_str())?))
             }
-            OpCode::Ping => {
-                let socket = client.get_socket();
-                let mut guard = socket.lock().await;
-                let result = guard.pong().await;
-                drop(guard);
-
-                if result.is_err() {
-                    Log::error(&format!("Error sending pong: {:?}", result));
-                    self.on_error(client).await;
-                    return Err(FastSocketError::ErrorSendingPong);
-                }
-
-                Ok(None)
-            }
             _ => Ok(None),
         }
     }
@@ -116,59 +199,125 @@ impl WebSocket {
         &self,
         fut: upgrade::UpgradeFut,
         app: Arc<App>,
+        connection_info: ConnectionInfo,
     ) -> Result<(), WebSocketError> {
-        let ws = fastwebsockets::FragmentCollector::new(fut.await?);
+        if !app.is_enabled() {
+            return self.reject(fut, FastSocketError::AppDisabledError).await;
+        }
+        let Some(_connection_slot) = app.try_connect() else {
+            return self.reject(fut, FastSocketError::ServerCapacityError).await;
+        };
+
+        let queue = OutboundQueue::for_app(&app);
+        let (connection, mut reader) = WebsocketConnection::new(fut.await?, queue);
         let client = Client::new(
-            WebsocketConnection::new(ws),
+            connection,
             app,
+            connection_info,
             self.channel_manager.clone(),
+            self.user_manager.clone(),
         );
         let mtx_client = Arc::new(client);
         let factory = MessageFactory::new(mtx_client.clone(), self.channel_manager.clone());
 
         self.on_open(mtx_client.clone()).await;
 
-        loop {
-            let lock_mtx = mtx_client.get_socket();
-            let mut guard = lock_mtx.lock().await;
-            let frame = guard.read().await;
+        let socket = mtx_client.get_socket();
+        let mut termination = mtx_client.on_terminate();
+        let mut shutdown = self.shutdown.subscribe();
+        let activity_timeout = Duration::from_secs(mtx_client.get_app().get_activity_timeout());
+        let pong_timeout = Duration::from_secs(mtx_client.get_app().get_pong_timeout());
+        let mut ping_sent = false;
 
-            if frame.is_err() {
-                drop(guard);
-                break;
-            }
+        loop {
+            let idle_timeout = if ping_sent { pong_timeout } else { activity_timeout };
+            let frame = tokio::select! {
+                Some(frame) = reader.read_frame() => ReadEvent::Frame(frame),
+                _ = termination.changed() => ReadEvent::Terminated,
+                _ = shutdown.wait_for(|shutdown| *shutdown) => ReadEvent::Shutdown,
+                _ = socket.closed() => ReadEvent::Closed,
+                _ = tokio::time::sleep(idle_timeout) => ReadEvent::Idle,
+            };
 
-            let payload = self.get_payload(mtx_client.clone(), frame?).await;
-            drop(guard);
-            if payload.is_err() {
-                Log::error(&format!("Error getting payload: {:?}", payload));
-                self.on_error(mtx_client.clone()).await;
-                continue;
-            }
+            let frame = match frame {
+                ReadEvent::Frame(frame) => frame,
+                ReadEvent::Terminated => {
+                    let reason = termination.borrow().clone();
+                    if let Some((code, reason)) = reason {
+                        let _ = socket.close(code, &reason).await;
+                    }
+                    break;
+                }
+                ReadEvent::Shutdown => {
+                    self.on_error(mtx_client.clone(), &FastSocketError::ReconnectError).await;
+                    break;
+                }
+                ReadEvent::Closed => {
+                    Log::debug(&format!("Writer closed: {}", mtx_client.get_socket_id()));
+                    break;
+                }
+                ReadEvent::Idle if !ping_sent => {
+                    let result = socket.ping().await;
+                    if result.is_err() {
+                        Log::error(&format!("Error sending ping: {:?}", result));
+                    }
+                    ping_sent = true;
+                    continue;
+                }
+                ReadEvent::Idle => {
+                    Log::debug(&format!("Pong timeout: {}", mtx_client.get_socket_id()));
+                    self.on_error(mtx_client.clone(), &FastSocketError::PongTimeoutError).await;
+                    break;
+                }
+            };
+            ping_sent = false;
 
-            let payload = payload.unwrap();
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(e) => {
+                    Log::debug(&format!("Error reading frame: {:?}", e));
+                    break;
+                }
+            };
 
-            if payload.is_none() {
-                continue;
+            let payload = self.get_payload(frame).await;
+            if let Err(FastSocketError::ConnectionClosed) = payload {
+                break;
             }
+            let payload = match payload {
+                Ok(Some(payload)) => payload,
+                Ok(None) => continue,
+                Err(e) => {
+                    Log::error(&format!("Error getting payload: {:?}", e));
+                    if self.on_error(mtx_client.clone(), &e).await {
+                        break;
+                    }
+                    continue;
+                }
+            };
 
-            let msg = factory.for_payload(payload.unwrap());
-            if msg.is_err() {
-                Log::error(&format!("Error creating message: {:?}", msg.err().unwrap()));
-                self.on_error(mtx_client.clone()).await;
-                continue;
-            }
+            let responder = match factory.for_payload(payload) {
+                Ok(responder) => responder,
+                Err(e) => {
+                    Log::error(&format!("Error creating message: {:?}", e));
+                    if self.on_error(mtx_client.clone(), &e).await {
+                        break;
+                    }
+                    continue;
+                }
+            };
 
-            let responder = msg.unwrap();
-            let result = responder.respond().await;
-            if result.is_err() {
-                Log::error(&format!("Error responding: {:?}", result));
-                self.on_error(mtx_client.clone()).await;
-                continue;
+            if let Err(e) = responder.respond().await {
+                Log::error(&format!("Error responding: {:?}", e));
+                if self.on_error(mtx_client.clone(), &e).await {
+                    break;
+                }
             }
         }
 
         self.on_close(mtx_client.clone()).await;
+        socket.shutdown();
+        let _ = tokio::time::timeout(CLOSE_TIMEOUT, socket.closed()).await;
 
         Log::debug("Connection closed");
 
@@ -1,52 +1,237 @@
-use fastwebsockets::{FragmentCollector, Frame, Payload as WsPayload};
+use fastwebsockets::{FragmentCollectorRead, Frame, Payload as WsPayload, WebSocket, WebSocketError, WebSocketWrite};
+use hyper::body::Bytes;
 use hyper::upgrade::Upgraded;
 use hyper_util::rt::TokioIo;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::io::{ReadHalf, WriteHalf};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
 use crate::errors::FastSocketError;
 use crate::logger::Log;
+use crate::outbound_queue::{Outbound, OutboundQueue};
 use crate::payload::{Payload, PayloadBuilder};
 
+type FrameReader = FragmentCollectorRead<ReadHalf<TokioIo<Upgraded>>>;
+type WebsocketWriter = WebSocketWrite<WriteHalf<TokioIo<Upgraded>>>;
+
+/// Frames the reader task may read ahead of the client's message loop.
+const READ_AHEAD: usize = 8;
+
+/// Read side of a client socket.
+///
+/// `read_frame` isn't cancel-safe, a partly read frame would corrupt the stream,
+/// so a reader task owns it and hands complete frames over a channel instead.
+pub struct WebsocketReader {
+    frames: mpsc::Receiver<Result<Frame<'static>, WebSocketError>>,
+    task: JoinHandle<()>,
+}
+
+impl WebsocketReader {
+    /// Waits for the next frame, `None` once the reader task is gone.
+    ///
+    /// Cancel-safe, no frame is lost when the future is dropped.
+    #[inline]
+    pub async fn read_frame(&mut self) -> Option<Result<Frame<'static>, WebSocketError>> {
+        self.frames.recv().await
+    }
+}
+
+impl Drop for WebsocketReader {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Write side of a client socket.
+///
+/// Frames are queued and written by a dedicated writer task, so senders never
+/// wait on the socket. What happens when a client can't keep up is decided by
+/// the queue's slow consumer policy.
+#[derive(Clone)]
 pub struct WebsocketConnection {
-    ws: FragmentCollector<TokioIo<Upgraded>>,
+    queue: Arc<OutboundQueue>,
+    closed: watch::Sender<bool>,
     public_key: String,
 }
 
 impl WebsocketConnection {
-    #[inline(always)]
-    pub fn new(ws: FragmentCollector<TokioIo<Upgraded>>) -> Self {
-        Self {
-            ws,
+    /// Splits the socket, spawns the reader and writer tasks and hands back the read side.
+    #[inline]
+    pub fn new(ws: WebSocket<TokioIo<Upgraded>>, queue: OutboundQueue) -> (Self, WebsocketReader) {
+        let (reader, writer) = ws.split(tokio::io::split);
+        let queue = Arc::new(queue);
+        let closed = watch::channel(false).0;
+
+        tokio::spawn(Self::write_loop(writer, queue.clone(), closed.clone()));
+
+        let connection = Self {
+            queue,
+            closed,
             public_key: String::with_capacity(64),
+        };
+
+        let (sender, frames) = mpsc::channel(READ_AHEAD);
+        let task = tokio::spawn(Self::read_loop(FragmentCollectorRead::new(reader), connection.clone(), sender));
+
+        (connection, WebsocketReader { frames, task })
+    }
+
+    /// Reads whole frames until the socket fails or the receiver is dropped.
+    async fn read_loop(
+        mut reader: FrameReader,
+        connection: WebsocketConnection,
+        frames: mpsc::Sender<Result<Frame<'static>, WebSocketError>>,
+    ) {
+        let mut send_obligated = |frame| connection.send_obligated(frame);
+        loop {
+            let frame = reader.read_frame(&mut send_obligated)
+                .await
+                .map(|frame| Frame::new(frame.fin, frame.opcode, None, WsPayload::Owned(frame.payload.to_vec())));
+
+            let failed = frame.is_err();
+            if frames.send(frame).await.is_err() || failed {
+                break;
+            }
+        }
+    }
+
+    async fn write_loop(
+        mut writer: WebsocketWriter,
+        queue: Arc<OutboundQueue>,
+        closed: watch::Sender<bool>,
+    ) {
+        while let Some(message) = queue.pop().await {
+            let result = match message {
+                Outbound::Text(buffer) | Outbound::Ping(buffer) => {
+                    Log::debug(&format!("Sending message: {} bytes", buffer.len()));
+                    writer.write_frame(Frame::text(WsPayload::Borrowed(&buffer))).await
+                }
+                Outbound::Control(opcode, payload) => {
+                    writer.write_frame(Frame::new(true, opcode, None, WsPayload::from(payload))).await
+                }
+                Outbound::Close(code, reason) => {
+                    Log::debug(&format!("Closing connection: {} {}", code, reason));
+                    let _ = writer.write_frame(Frame::close(code, reason.as_bytes())).await;
+                    break;
+                }
+            };
+
+            if let Err(e) = result {
+                Log::error(&format!("Error writing frame: {:?}", e));
+                break;
+            }
         }
+
+        queue.close();
+        closed.send_replace(true);
     }
 
-    #[inline(always)]
-    pub async fn write(&mut self, frame: Frame<'_>) -> Result<(), fastwebsockets::WebSocketError> {
-        Log::debug(&format!("Sending message: {:?}", frame.payload));
-        self.ws.write_frame(frame).await
+    #[inline]
+    async fn enqueue(&self, message: Outbound) -> Result<(), FastSocketError> {
+        match self.queue.push(message) {
+            Err(FastSocketError::ReconnectError) => {
+                self.evict();
+                Ok(())
+            }
+            result => result.map_err(|_| FastSocketError::FailedToSendPayloadError),
+        }
     }
 
-    #[inline(always)]
-    pub async fn read(&mut self) -> Result<fastwebsockets::Frame<'_>, fastwebsockets::WebSocketError> {
-        self.ws.read_frame().await
+    /// Drops a client that kept overflowing its queue, asking it to reconnect.
+    fn evict(&self) {
+        let error = FastSocketError::ReconnectError;
+        let payload = PayloadBuilder::default()
+            .event("pusher:error")
+            .add_data("code", error.error_code())
+            .add_data("message", error.to_string())
+            .build()
+            .and_then(|payload| payload.compile(None));
+
+        if let Ok(buffer) = payload {
+            if self.queue.evict(Bytes::from(buffer), 4200, &error.to_string()) {
+                Log::debug(&format!("Evicted slow consumer, {} messages dropped", self.get_dropped_count()));
+            }
+        }
     }
 
+    /// Queues the frames the reader is obliged to answer, e.g. pongs and close echoes.
+    #[inline]
+    pub fn send_obligated(&self, frame: Frame<'_>) -> impl Future<Output = Result<(), FastSocketError>> + '_ {
+        let message = Outbound::Control(frame.opcode, frame.payload.to_vec());
+        self.enqueue(message)
+    }
 
     #[inline]
-    pub async fn send(&mut self, payload: &Payload) -> Result<(), FastSocketError> {
-        let key = (!self.public_key.is_empty()).then(|| self.public_key.as_str());
+    pub async fn send(&self, payload: &Payload) -> Result<(), FastSocketError> {
+        let key = (!self.public_key.is_empty()).then_some(self.public_key.as_str());
 
         let buffer = payload.compile(key.map(String::from))?;
-        let ws_payload = WsPayload::from(buffer);
-        let frame = Frame::text(ws_payload);
-        self.write(frame)
+        self.enqueue(Outbound::Text(Bytes::from(buffer))).await
+    }
+
+    /// Sends a frame compiled once for many recipients.
+    ///
+    /// Connections with their own encryption key still compile `payload` for themselves.
+    #[inline]
+    pub async fn send_shared(&self, payload: &Payload, frame: &Bytes) -> Result<(), FastSocketError> {
+        if !self.public_key.is_empty() {
+            return self.send(payload).await;
+        }
+
+        self.enqueue(Outbound::Text(frame.clone())).await
+    }
+
+    #[inline]
+    pub async fn send_error(&self, error: &FastSocketError) -> Result<(), FastSocketError> {
+        let payload = PayloadBuilder::default()
+            .event("pusher:error")
+            .add_data("code", error.error_code())
+            .add_data("message", error.to_string())
+            .build()?;
+
+        self.send(&payload).await
+    }
+
+    #[inline]
+    pub async fn close(&self, code: u16, reason: &str) -> Result<(), FastSocketError> {
+        self.enqueue(Outbound::Close(code, reason.to_string()))
             .await
-            .map_err(|_| FastSocketError::FailedToSendPayloadError)?;
+            .map_err(|_| FastSocketError::ConnectionClosed)
+    }
 
-        Ok(())
+    /// Stops the writer task once the frames already queued are written.
+    #[inline]
+    pub fn shutdown(&self) {
+        self.queue.close();
+    }
+
+    /// Resolves once the writer task is gone, e.g. after the client got evicted.
+    #[inline]
+    pub async fn closed(&self) {
+        let mut receiver = self.closed.subscribe();
+        let _ = receiver.wait_for(|closed| *closed).await;
+    }
+
+    /// Messages dropped so far because the client couldn't keep up.
+    #[inline]
+    pub fn get_dropped_count(&self) -> u64 {
+        self.queue.get_dropped_count()
+    }
+
+    #[inline]
+    pub async fn ping(&self) -> Result<(), FastSocketError> {
+        Log::debug("Sending ping");
+        let buffer = PayloadBuilder::default()
+            .event("pusher:ping")
+            .build()?
+            .compile(None)?;
+
+        self.enqueue(Outbound::Ping(Bytes::from(buffer))).await
     }
 
     #[inline]
-    pub async fn pong(&mut self) -> Result<(), FastSocketError> {
+    pub async fn pong(&self) -> Result<(), FastSocketError> {
         Log::debug("Sending pong");
         let payload = PayloadBuilder::default()
             .event("pusher:pong")